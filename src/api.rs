@@ -0,0 +1,221 @@
+use std::thread;
+
+use anyhow::{Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{error, info, warn};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    config::{self, ApiConfig},
+    process,
+};
+
+//启动内置的HTTP控制接口，所有请求都需要通过basic auth认证
+pub fn start_server(api_config: ApiConfig) -> Result<()> {
+    let address = format!("{}:{}", api_config.host, api_config.port);
+    let server = Server::http(&address)
+        .map_err(|e| Error::msg(format!("start api server on {} failed: {}", address, e)))?;
+    info!("api server is listening on {}", address);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let api_config = api_config.clone();
+            //服务的启停可能比较耗时，每个请求单独处理，避免阻塞其它请求
+            thread::spawn(move || handle_request(request, &api_config));
+        }
+        error!("api server has been stopped!");
+    });
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct MessageBody {
+    message: String,
+}
+
+fn handle_request(request: Request, api_config: &ApiConfig) {
+    let auth = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .map(|h| h.value.as_str().to_string());
+    if !is_authorized(auth.as_deref(), api_config) {
+        let response = json_response(401, &message("unauthorized")).with_header(
+            Header::from_bytes("WWW-Authenticate", "Basic realm=\"process-compose\"").unwrap(),
+        );
+        respond(request, response);
+        return;
+    }
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let response = match route(request.method(), &segments) {
+        Ok(response) => response,
+        Err(err) => {
+            warn!("api request {} {} failed: {}", request.method(), url, err);
+            json_response(400, &message(&err.to_string()))
+        }
+    };
+    respond(request, response);
+}
+
+fn route(method: &Method, segments: &[&str]) -> Result<Response<std::io::Cursor<Vec<u8>>>> {
+    match (method, segments) {
+        (Method::Get, ["api", "services"]) => {
+            Ok(json_response(200, &process::status::get_all_proc_status()))
+        }
+        (Method::Get, ["api", "services", name]) => {
+            let status = process::status::get_all_proc_status()
+                .into_iter()
+                .find(|s| s.name == *name);
+            match status {
+                Some(status) => Ok(json_response(200, &status)),
+                None => Ok(json_response(
+                    404,
+                    &message(&format!("service {} not found", name)),
+                )),
+            }
+        }
+        (Method::Get, ["api", "config"]) => {
+            let mut config = config::current_config();
+            //不对外暴露接口密码
+            if let Some(api) = config.api.as_mut() {
+                api.password = "******".to_string();
+            }
+            Ok(json_response(200, &config))
+        }
+        (Method::Post, ["api", "services", action]) => {
+            let all_services = process::status::get_all_process_name();
+            process::manager::run_operator_action(action, all_services)?;
+            Ok(json_response(
+                200,
+                &message(&format!("{} all services", action)),
+            ))
+        }
+        (Method::Post, ["api", "services", name, "scale", replicas]) => {
+            let replicas = replicas
+                .parse::<u32>()
+                .map_err(|_| Error::msg(format!("invalid replicas: {}", replicas)))?;
+            process::manager::scale_service(name, replicas)?;
+            Ok(json_response(
+                200,
+                &message(&format!("scale {} to {}", name, replicas)),
+            ))
+        }
+        (Method::Post, ["api", "services", name, action]) => {
+            if !process::status::service_exists(name) {
+                return Ok(json_response(
+                    404,
+                    &message(&format!("service {} not found", name)),
+                ));
+            }
+            process::manager::run_operator_action(action, vec![name.to_string()])?;
+            Ok(json_response(
+                200,
+                &message(&format!("{} {}", action, name)),
+            ))
+        }
+        _ => Ok(json_response(404, &message("not found"))),
+    }
+}
+
+fn is_authorized(auth: Option<&str>, api_config: &ApiConfig) -> bool {
+    //非Basic认证直接拒绝，不做解码
+    let encoded = match auth.and_then(|v| v.strip_prefix("Basic ")) {
+        Some(encoded) => encoded,
+        None => return false,
+    };
+    let credentials = match STANDARD.decode(encoded.trim()) {
+        Ok(credentials) => credentials,
+        Err(_) => return false,
+    };
+    let (username, password) = match credentials.iter().position(|b| *b == b':') {
+        Some(i) => (&credentials[..i], &credentials[i + 1..]),
+        None => return false,
+    };
+    //用户名和密码分别做常量时间比较，避免通过响应时间逐字节猜测凭据
+    let username_ok = constant_time_eq(username, api_config.username.as_bytes());
+    let password_ok = constant_time_eq(password, api_config.password.as_bytes());
+    username_ok & password_ok
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn message(msg: &str) -> MessageBody {
+    MessageBody {
+        message: msg.to_string(),
+    }
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> Response<std::io::Cursor<Vec<u8>>> {
+    let body = serde_json::to_string(body).unwrap_or_else(|e| format!("{{\"message\":\"{}\"}}", e));
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn respond(request: Request, response: Response<std::io::Cursor<Vec<u8>>>) {
+    if let Err(err) = request.respond(response) {
+        warn!("send api response failed: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_config() -> ApiConfig {
+        ApiConfig {
+            enable: true,
+            host: "127.0.0.1".to_string(),
+            port: "9000".to_string(),
+            username: "admin".to_string(),
+            password: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let config = api_config();
+        let valid = format!("Basic {}", STANDARD.encode("admin:secret"));
+        let invalid = format!("Basic {}", STANDARD.encode("admin:wrong"));
+        assert!(is_authorized(Some(&valid), &config));
+        assert!(!is_authorized(Some(&invalid), &config));
+        let wrong_user = format!("Basic {}", STANDARD.encode("root:secret"));
+        assert!(!is_authorized(Some(&wrong_user), &config));
+        let no_colon = format!("Basic {}", STANDARD.encode("adminsecret"));
+        assert!(!is_authorized(Some(&no_colon), &config));
+        assert!(!is_authorized(Some("Basic not-base64!"), &config));
+        assert!(!is_authorized(Some("Bearer token"), &config));
+        assert!(!is_authorized(None, &config));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_route_not_found() {
+        let response = route(&Method::Delete, &["api", "services"]).unwrap();
+        assert_eq!(response.status_code().0, 404);
+    }
+
+    #[test]
+    fn test_action_on_unknown_service() {
+        let response = route(
+            &Method::Post,
+            &["api", "services", "no-such-service", "restart"],
+        )
+        .unwrap();
+        assert_eq!(response.status_code().0, 404);
+    }
+}
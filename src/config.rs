@@ -1,11 +1,16 @@
+use std::fmt;
 use std::str::FromStr;
 use std::{collections::HashMap, fs::File, io::Read, sync::RwLock};
 
 use anyhow::{Error, Result};
+use chrono_tz::Tz;
+use cron::Schedule;
+use log::LevelFilter;
+use regex::Regex;
 use serde::de::Error as DeError;
 use serde::{de::Unexpected, Deserialize, Deserializer, Serialize};
 
-use crate::{env, health::HealthCheckType};
+use crate::{env, health::HealthCheckType, process::output::LinePattern};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GlobalConfig {
@@ -42,22 +47,320 @@ fn default_sys_service_desc() -> String {
     "Process Monitoring and Management Tool".to_string()
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ServiceConfig {
     #[serde(default = "default_service_name")]
     pub name: String,
+    #[serde(default, rename = "type")]
+    pub service_type: ServiceType,
     pub log_redirect: bool,
     pub log_pattern: Option<String>,
+    //重定向日志文件的切分及清理策略
+    #[serde(default)]
+    pub log_rotation: LogRotationConfig,
+    //未单独配置readiness或liveness时，healthcheck同时作为就绪检查及存活检查
     pub healthcheck: Option<HealthCheckConfig>,
+    //就绪检查：决定服务的健康状态，依赖该服务的服务等待其通过，检查失败不会重启服务
+    pub readiness: Option<HealthCheckConfig>,
+    //存活检查：失败次数超过max_failures后重启服务，不影响服务的健康状态
+    pub liveness: Option<HealthCheckConfig>,
     pub start_cmd: Vec<String>,
-    pub depends_on: Option<Vec<String>>,
+    //所依赖的服务及其需要满足的启动条件，支持服务名列表和{服务名: {condition: ...}}两种写法
+    #[serde(default, deserialize_with = "deserialize_depends_on")]
+    pub depends_on: Option<HashMap<String, DependsOnConfig>>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(default)]
+    pub restart_backoff: RestartBackoffConfig,
+    //restart_window时间内最多允许自动重启的次数，超过后服务进入Failed状态，不配置则不限制
+    pub max_restarts: Option<u32>,
+    #[serde(default = "default_restart_window")]
+    pub restart_window: i32,
+    //服务进程额外的环境变量，支持map和KEY=VALUE列表两种写法
+    #[serde(default, deserialize_with = "deserialize_environment")]
+    pub environment: HashMap<String, String>,
+    //dotenv格式的环境变量文件，相对路径基于服务目录
+    #[serde(default, deserialize_with = "deserialize_string_or_list")]
+    pub env_file: Vec<String>,
+    //服务的工作目录，相对路径基于配置文件所在目录，默认为{配置文件所在目录}/{服务名}
+    pub working_dir: Option<String>,
+    //停止服务时发送给进程的信号
+    #[serde(default)]
+    pub stop_signal: StopSignal,
+    //停止服务时等待进程退出的时间(秒)，包括stop_cmd的执行时间，超时后强制杀掉进程
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: i32,
+    //发送停止信号前执行的命令，如调用服务的管理接口
+    #[serde(default)]
+    pub stop_cmd: Vec<String>,
+    //服务生命周期各阶段执行的钩子命令
+    pub pre_start: Option<HookConfig>,
+    pub post_start: Option<HookConfig>,
+    pub pre_stop: Option<HookConfig>,
+    pub post_stop: Option<HookConfig>,
+    //按计划定时运行服务，而不是保持服务常驻
+    pub schedule: Option<ScheduleConfig>,
+    //同时运行的实例数，第0个实例使用服务名，其余实例名为{服务名}-{序号}
+    #[serde(default = "default_replicas")]
+    pub replicas: u32,
+}
+
+fn default_replicas() -> u32 {
+    1
+}
+
+//与反序列化时的默认值保持一致，默认运行一个实例
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        ServiceConfig {
+            name: String::default(),
+            service_type: ServiceType::default(),
+            log_redirect: false,
+            log_pattern: None,
+            log_rotation: LogRotationConfig::default(),
+            healthcheck: None,
+            readiness: None,
+            liveness: None,
+            start_cmd: Vec::new(),
+            depends_on: None,
+            restart: RestartPolicy::default(),
+            restart_backoff: RestartBackoffConfig::default(),
+            max_restarts: None,
+            restart_window: 0,
+            environment: HashMap::new(),
+            env_file: Vec::new(),
+            working_dir: None,
+            stop_signal: StopSignal::default(),
+            stop_timeout: 0,
+            stop_cmd: Vec::new(),
+            pre_start: None,
+            post_start: None,
+            pre_stop: None,
+            post_stop: None,
+            schedule: None,
+            replicas: default_replicas(),
+        }
+    }
+}
+
+//日志文件每天切分一次，单个文件超过max_size时也会切分
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LogRotationConfig {
+    //单个日志文件的最大大小(MB)，不配置则只按天切分
+    pub max_size: Option<u64>,
+    //最多保留的已切分日志文件数，不配置则不限制
+    pub max_files: Option<usize>,
+    //已切分日志文件的保留天数，不配置则不限制
+    pub retention_days: Option<u64>,
+    //是否使用gzip压缩已切分的日志文件
+    #[serde(default)]
+    pub compress: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleConfig {
+    //cron表达式，格式为：秒 分 时 日 月 星期 [年]
+    pub cron: String,
+    //时区，如Asia/Shanghai，默认使用本地时区
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+    //单次运行的最长时间(秒)，超时后停止本次运行
+    pub max_runtime: Option<i32>,
+}
+
+//到达计划时间时上一次运行尚未结束的处理方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlapPolicy {
+    //跳过本次运行（默认）
+    #[default]
+    Skip,
+    //等待上一次运行结束后再运行
+    Queue,
+    //停止上一次运行后重新运行
+    KillPrevious,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HookConfig {
+    //与start_cmd格式相同，在服务的工作目录下执行
+    pub cmd: Vec<String>,
+    //超时时间(秒)，超时后钩子命令会被杀掉并视为失败
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: i32,
+    //在服务环境变量的基础上额外设置的环境变量
+    #[serde(default, deserialize_with = "deserialize_environment")]
+    pub environment: HashMap<String, String>,
+    #[serde(default)]
+    pub on_failure: HookFailurePolicy,
+}
+
+fn default_hook_timeout() -> i32 {
+    30
+}
+
+//钩子命令执行失败时的处理方式
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailurePolicy {
+    //中止当前的启动或停止操作
+    #[default]
+    Abort,
+    //记录日志后继续
+    Ignore,
+}
+
+fn default_stop_timeout() -> i32 {
+    10
+}
+
+//停止服务时使用的信号，windows下固定发送CTRL_BREAK和CTRL_C事件
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum StopSignal {
+    #[default]
+    #[serde(rename = "SIGTERM")]
+    Term,
+    #[serde(rename = "SIGINT")]
+    Int,
+    #[serde(rename = "SIGQUIT")]
+    Quit,
+    #[serde(rename = "SIGHUP")]
+    Hup,
+    #[serde(rename = "SIGKILL")]
+    Kill,
+    #[serde(rename = "SIGUSR1")]
+    Usr1,
+    #[serde(rename = "SIGUSR2")]
+    Usr2,
+}
+
+impl fmt::Display for StopSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StopSignal::Term => "SIGTERM",
+            StopSignal::Int => "SIGINT",
+            StopSignal::Quit => "SIGQUIT",
+            StopSignal::Hup => "SIGHUP",
+            StopSignal::Kill => "SIGKILL",
+            StopSignal::Usr1 => "SIGUSR1",
+            StopSignal::Usr2 => "SIGUSR2",
+        };
+        f.write_str(name)
+    }
+}
+
+fn default_restart_window() -> i32 {
+    60
 }
 
 fn default_service_name() -> String {
     "".to_string()
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+//服务的运行方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceType {
+    //常驻运行的服务（默认）
+    #[default]
+    Simple,
+    //运行结束即完成的一次性任务，如数据库迁移，退出码为0时进入Completed状态
+    Oneshot,
+}
+
+//服务自行退出后的重启策略
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    //不自动重启（默认）
+    #[default]
+    No,
+    //只要进程自行退出就重启
+    Always,
+    //仅在退出码非0或被信号终止时重启
+    OnFailure,
+    //与always相同，但被运维人员主动停止的服务不会被重新拉起
+    UnlessStopped,
+}
+
+//依赖服务需要满足的条件
+//与docker compose的写法保持一致
+#[allow(clippy::enum_variant_names)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DependsOnCondition {
+    //依赖服务已启动
+    ServiceStarted,
+    //依赖服务健康检查通过，依赖服务未配置健康检查时等同于service_started
+    #[default]
+    ServiceHealthy,
+    //依赖服务运行结束且退出码为0
+    ServiceCompletedSuccessfully,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DependsOnConfig {
+    #[serde(default)]
+    pub condition: DependsOnCondition,
+}
+
+//重启延迟的指数退避配置，时间单位为秒
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RestartBackoffConfig {
+    #[serde(default = "default_backoff_initial_delay")]
+    pub initial_delay: i32,
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+    #[serde(default = "default_backoff_max_delay")]
+    pub max_delay: i32,
+}
+
+impl Default for RestartBackoffConfig {
+    fn default() -> Self {
+        RestartBackoffConfig {
+            initial_delay: default_backoff_initial_delay(),
+            multiplier: default_backoff_multiplier(),
+            max_delay: default_backoff_max_delay(),
+        }
+    }
+}
+
+fn default_backoff_initial_delay() -> i32 {
+    1
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_backoff_max_delay() -> i32 {
+    60
+}
+
+impl ServiceConfig {
+    pub fn readiness_check(&self) -> Option<&HealthCheckConfig> {
+        self.readiness.as_ref().or(self.healthcheck.as_ref())
+    }
+
+    //log类型只用于判断服务是否就绪
+    pub fn liveness_check(&self) -> Option<&HealthCheckConfig> {
+        self.liveness
+            .as_ref()
+            .or(self.healthcheck.as_ref())
+            .filter(|check| check.test_type != HealthCheckType::Log)
+    }
+
+    fn health_checks_mut(&mut self) -> [(&'static str, Option<&mut HealthCheckConfig>); 3] {
+        [
+            ("healthcheck", self.healthcheck.as_mut()),
+            ("readiness", self.readiness.as_mut()),
+            ("liveness", self.liveness.as_mut()),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HealthCheckConfig {
     #[serde(
         default = "default_health_check_type",
@@ -66,11 +369,118 @@ pub struct HealthCheckConfig {
     pub test_type: HealthCheckType,
     #[serde(default = "default_test_target")]
     pub test_target: String,
+    //cmd类型的命令列表，直接执行而不经过shell，未配置时test_target通过sh -c(Windows下为cmd /C)执行
+    #[serde(default)]
+    pub test_cmd: Vec<String>,
     #[serde(default = "default_check_interval")]
     pub interval: i32,
-    #[serde(default = "default_max_failures")]
+    //存活检查连续失败超过该次数后重启服务
+    #[serde(default = "default_max_failures", alias = "retries")]
     pub max_failures: i32,
+    //就绪检查连续成功该次数后服务才被视为健康
+    #[serde(default = "default_success_threshold")]
+    pub success_threshold: u32,
     pub start_period: Option<i32>,
+    //单次检查的超时时间(秒)，默认5秒，超时按检查失败处理，log类型不支持
+    pub timeout: Option<i32>,
+    //log类型等待匹配的日志出现的最长时间(秒)，超时后服务被停止并标记为Failed，不配置则一直等待
+    pub log_timeout: Option<i32>,
+    //http类型的请求及判断条件
+    #[serde(default)]
+    pub http: HttpCheckConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HttpCheckConfig {
+    #[serde(default = "default_http_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    //期望的状态码，支持200、"200-299"及"2xx"三种写法，不配置时要求状态码为2xx
+    #[serde(default, deserialize_with = "deserialize_expected_status")]
+    pub expected_status: Vec<StatusRange>,
+    //响应内容需匹配的正则表达式
+    pub body_regex: Option<String>,
+    //响应内容按JSON解析后，pointer(如/status)处的值需等于value
+    pub json_match: Option<JsonMatchConfig>,
+    //最多跟随的重定向次数，为0时不跟随重定向
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    //不校验TLS证书，用于自签名证书的内部服务
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+impl Default for HttpCheckConfig {
+    fn default() -> Self {
+        HttpCheckConfig {
+            method: default_http_method(),
+            headers: HashMap::new(),
+            expected_status: Vec::new(),
+            body_regex: None,
+            json_match: None,
+            max_redirects: default_max_redirects(),
+            insecure: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonMatchConfig {
+    pub pointer: String,
+    pub value: serde_json::Value,
+}
+
+//状态码范围，两端都包含
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct StatusRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl StatusRange {
+    pub fn contains(&self, status: u16) -> bool {
+        self.start <= status && status <= self.end
+    }
+}
+
+impl FromStr for StatusRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::msg(format!("invalid status code: {}", s));
+        let s = s.trim();
+        let range = if let Some(class) = s.strip_suffix("xx") {
+            let class: u16 = class.parse().map_err(|_| invalid())?;
+            StatusRange {
+                start: class * 100,
+                end: class * 100 + 99,
+            }
+        } else if let Some((start, end)) = s.split_once('-') {
+            StatusRange {
+                start: start.trim().parse().map_err(|_| invalid())?,
+                end: end.trim().parse().map_err(|_| invalid())?,
+            }
+        } else {
+            let code = s.parse().map_err(|_| invalid())?;
+            StatusRange {
+                start: code,
+                end: code,
+            }
+        };
+        if range.start < 100 || range.end > 599 || range.start > range.end {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
+
+fn default_http_method() -> String {
+    "GET".to_string()
+}
+
+fn default_max_redirects() -> usize {
+    10
 }
 
 fn default_check_interval() -> i32 {
@@ -81,6 +491,10 @@ fn default_max_failures() -> i32 {
     1
 }
 
+fn default_success_threshold() -> u32 {
+    1
+}
+
 fn default_health_check_type() -> HealthCheckType {
     HealthCheckType::Proccess
 }
@@ -98,7 +512,124 @@ where
         .map_err(|_| D::Error::custom(format!("invalid health check type: {}", s)))
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StatusDef {
+    Code(u16),
+    Range(String),
+}
+
+fn deserialize_expected_status<'de, D>(deserializer: D) -> Result<Vec<StatusRange>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<StatusDef>::deserialize(deserializer)?
+        .into_iter()
+        .map(|status| match status {
+            StatusDef::Code(code) => StatusRange::from_str(&code.to_string()),
+            StatusDef::Range(range) => StatusRange::from_str(&range),
+        })
+        .collect::<Result<_>>()
+        .map_err(D::Error::custom)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EnvironmentDef {
+    Map(HashMap<String, serde_yaml::Value>),
+    List(Vec<String>),
+}
+
+//没有配置值的变量(如`- KEY`或`KEY:`)沿用process-compose自身环境中的值
+fn deserialize_environment<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut environment = HashMap::new();
+    match EnvironmentDef::deserialize(deserializer)? {
+        EnvironmentDef::Map(map) => {
+            for (key, value) in map {
+                let value = match value {
+                    serde_yaml::Value::Null => std::env::var(&key).ok(),
+                    serde_yaml::Value::String(s) => Some(s),
+                    serde_yaml::Value::Number(n) => Some(n.to_string()),
+                    serde_yaml::Value::Bool(b) => Some(b.to_string()),
+                    _ => {
+                        return Err(D::Error::custom(format!(
+                            "invalid value of environment variable: {}",
+                            key
+                        )))
+                    }
+                };
+                if let Some(value) = value {
+                    environment.insert(key, value);
+                }
+            }
+        }
+        EnvironmentDef::List(list) => {
+            for item in list {
+                match item.split_once('=') {
+                    Some((key, value)) => {
+                        environment.insert(key.to_string(), value.to_string());
+                    }
+                    None => {
+                        if let Ok(value) = std::env::var(&item) {
+                            environment.insert(item, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(environment)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrList {
+    String(String),
+    List(Vec<String>),
+}
+
+fn deserialize_string_or_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(s) => Ok(vec![s]),
+        StringOrList::List(list) => Ok(list),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DependsOnDef {
+    List(Vec<String>),
+    Map(HashMap<String, Option<DependsOnConfig>>),
+}
+
+//列表写法中的依赖服务均使用默认的service_healthy条件
+fn deserialize_depends_on<'de, D>(
+    deserializer: D,
+) -> Result<Option<HashMap<String, DependsOnConfig>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let depends_on = match Option::<DependsOnDef>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(DependsOnDef::List(list)) => list
+            .into_iter()
+            .map(|name| (name, DependsOnConfig::default()))
+            .collect(),
+        Some(DependsOnDef::Map(map)) => map
+            .into_iter()
+            .map(|(name, config)| (name, config.unwrap_or_default()))
+            .collect(),
+    };
+    Ok(Some(depends_on))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ApiConfig {
     pub enable: bool,
     pub host: String,
@@ -113,6 +644,14 @@ const MAX_DEPTH: i32 = 5;
 static CONFIG: RwLock<Option<GlobalConfig>> = RwLock::new(None);
 
 pub fn load_config() -> Result<GlobalConfig> {
+    let config = read_config()?;
+    let mut config_global = CONFIG.write().unwrap();
+    *config_global = Some(config.clone());
+    Ok(config)
+}
+
+//读取并校验配置文件，不会影响当前生效的配置
+pub fn read_config() -> Result<GlobalConfig> {
     let mut config_file_path = env::ROOT_DIR.clone();
     config_file_path.push(CONFIG_FILE_NAME);
     let mut file = File::open(config_file_path)?;
@@ -122,12 +661,452 @@ pub fn load_config() -> Result<GlobalConfig> {
     config.services.iter_mut().for_each(|(name, service)| {
         service.name = name.clone();
     });
-    let mut config_global = CONFIG.write().unwrap();
-    *config_global = Some(config.clone());
+    interpolate_config(&mut config, &load_dot_env()?)?;
+    validate_config(&config)?;
     Ok(config)
 }
 
-//仅用于测试
+fn validate_config(config: &GlobalConfig) -> Result<()> {
+    if !config.log_level.is_empty() && LevelFilter::from_str(&config.log_level).is_err() {
+        return Err(Error::msg(format!(
+            "invalid log_level: {}",
+            config.log_level
+        )));
+    }
+    for service in config.services.values() {
+        for dep in service.depends_on.iter().flat_map(|deps| deps.keys()) {
+            if !config.services.contains_key(dep) {
+                return Err(Error::msg(format!(
+                    "services.{}.depends_on: service {} is not defined",
+                    service.name, dep
+                )));
+            }
+        }
+    }
+    for service in config.services.values() {
+        if service.replicas == 0 {
+            return Err(Error::msg(format!(
+                "services.{}.replicas: must be at least 1",
+                service.name
+            )));
+        }
+        if let Some(pattern) = service.log_pattern.as_deref() {
+            LinePattern::parse(pattern)
+                .map_err(|e| Error::msg(format!("services.{}.log_pattern: {}", service.name, e)))?;
+        }
+        let mut instance = instance_config(service, 0)?;
+        for (check_name, check) in instance.health_checks_mut() {
+            let Some(check) = check else { continue };
+            let field = format!("services.{}.{}", service.name, check_name);
+            validate_health_check(&field, check)?;
+            if check_name == "liveness" && check.test_type == HealthCheckType::Log {
+                return Err(Error::msg(format!(
+                    "{}.test_type: log can only be used for readiness",
+                    field
+                )));
+            }
+        }
+        if service.log_rotation.max_size == Some(0) {
+            return Err(Error::msg(format!(
+                "services.{}.log_rotation.max_size: must be greater than 0",
+                service.name
+            )));
+        }
+        if service.replicas > 1 && service.schedule.is_some() {
+            return Err(Error::msg(format!(
+                "services.{}: replicas can not be used together with schedule",
+                service.name
+            )));
+        }
+        for instance in instance_configs(service, service.replicas)?.iter().skip(1) {
+            if config.services.contains_key(&instance.name) {
+                return Err(Error::msg(format!(
+                    "services.{}: instance name {} conflicts with another service",
+                    service.name, instance.name
+                )));
+            }
+        }
+        if let Some(schedule) = service.schedule.as_ref() {
+            Schedule::from_str(&schedule.cron).map_err(|e| {
+                Error::msg(format!(
+                    "services.{}.schedule.cron: invalid cron expression \"{}\": {}",
+                    service.name, schedule.cron, e
+                ))
+            })?;
+            if let Some(timezone) = schedule.timezone.as_ref() {
+                Tz::from_str(timezone).map_err(|e| {
+                    Error::msg(format!(
+                        "services.{}.schedule.timezone: {}",
+                        service.name, e
+                    ))
+                })?;
+            }
+        }
+    }
+    let services = config.services.values().cloned().collect();
+    analyze_service_dependencies(&services)?;
+    Ok(())
+}
+
+fn validate_health_check(field: &str, check: &HealthCheckConfig) -> Result<()> {
+    if check.success_threshold == 0 {
+        return Err(Error::msg(format!(
+            "{}.success_threshold must be greater than 0",
+            field
+        )));
+    }
+    if check.timeout.is_some_and(|t| t <= 0) {
+        return Err(Error::msg(format!(
+            "{}.timeout must be greater than 0",
+            field
+        )));
+    }
+    if check.log_timeout.is_some_and(|t| t <= 0) {
+        return Err(Error::msg(format!(
+            "{}.log_timeout must be greater than 0",
+            field
+        )));
+    }
+    //log类型等待日志的时间与单次检查的超时时间含义不同，分别使用两个字段
+    let is_log = check.test_type == HealthCheckType::Log;
+    if is_log && check.timeout.is_some() {
+        return Err(Error::msg(format!(
+            "{}.timeout is not supported by log checks, use log_timeout instead",
+            field
+        )));
+    }
+    if !is_log && check.log_timeout.is_some() {
+        return Err(Error::msg(format!(
+            "{}.log_timeout can only be used with test_type: log",
+            field
+        )));
+    }
+    if is_log {
+        Regex::new(&check.test_target)
+            .map_err(|e| Error::msg(format!("{}.test_target: invalid regex: {}", field, e)))?;
+    }
+    if check.test_type == HealthCheckType::Http {
+        validate_http_check(&format!("{}.http", field), &check.http)?;
+    }
+    Ok(())
+}
+
+//http方式的检查在加载配置时校验请求方法、请求头及正则表达式
+fn validate_http_check(http_field: &str, http: &HttpCheckConfig) -> Result<()> {
+    let field = |name: &str| format!("{}.{}", http_field, name);
+    reqwest::Method::from_bytes(http.method.as_bytes()).map_err(|_| {
+        Error::msg(format!(
+            "{}: invalid method {}",
+            field("method"),
+            http.method
+        ))
+    })?;
+    for (name, value) in http.headers.iter() {
+        reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| Error::msg(format!("{}: {}", field("headers"), e)))?;
+        reqwest::header::HeaderValue::from_str(value)
+            .map_err(|e| Error::msg(format!("{}.{}: {}", field("headers"), name, e)))?;
+    }
+    if let Some(body_regex) = http.body_regex.as_deref() {
+        Regex::new(body_regex)
+            .map_err(|e| Error::msg(format!("{}: invalid regex: {}", field("body_regex"), e)))?;
+    }
+    if let Some(json_match) = http.json_match.as_ref() {
+        if !json_match.pointer.is_empty() && !json_match.pointer.starts_with('/') {
+            return Err(Error::msg(format!(
+                "{}: pointer must be empty or start with /",
+                field("json_match")
+            )));
+        }
+    }
+    Ok(())
+}
+
+//新旧配置中服务的差异
+#[derive(Debug, Default, PartialEq)]
+pub struct ServicesDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+pub fn diff_services(
+    old: &HashMap<String, ServiceConfig>,
+    new: &HashMap<String, ServiceConfig>,
+) -> ServicesDiff {
+    let mut diff = ServicesDiff::default();
+    for (name, service) in new.iter() {
+        match old.get(name) {
+            None => diff.added.push(name.clone()),
+            Some(old_service) if old_service != service => diff.changed.push(name.clone()),
+            _ => {}
+        }
+    }
+    diff.removed = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .cloned()
+        .collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    diff
+}
+
+const DOT_ENV_FILE_NAME: &str = ".env";
+
+//读取配置文件同目录下的.env文件，作为配置变量的补充来源
+fn load_dot_env() -> Result<HashMap<String, String>> {
+    let path = env::ROOT_DIR.join(DOT_ENV_FILE_NAME);
+    let mut vars = HashMap::new();
+    if !path.exists() {
+        return Ok(vars);
+    }
+    for item in dotenvy::from_path_iter(&path)? {
+        let (key, value) =
+            item.map_err(|e| Error::msg(format!("parse {} failed: {}", path.display(), e)))?;
+        vars.insert(key, value);
+    }
+    Ok(vars)
+}
+
+//展开配置中的变量引用，进程环境变量优先于.env文件中的变量
+fn interpolate_config(config: &mut GlobalConfig, dot_env: &HashMap<String, String>) -> Result<()> {
+    let lookup = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .or_else(|| dot_env.get(name).cloned())
+    };
+    config.app_data_home = interpolate(&config.app_data_home, "app_data_home", &lookup)?;
+    for (name, service) in config.services.iter_mut() {
+        if let Some(working_dir) = service.working_dir.as_mut() {
+            let field = format!("services.{}.working_dir", name);
+            *working_dir = interpolate(working_dir, &field, &lookup)?;
+        }
+        for (i, arg) in service.start_cmd.iter_mut().enumerate() {
+            let field = format!("services.{}.start_cmd[{}]", name, i);
+            *arg = interpolate(arg, &field, &lookup)?;
+        }
+        for (i, arg) in service.stop_cmd.iter_mut().enumerate() {
+            let field = format!("services.{}.stop_cmd[{}]", name, i);
+            *arg = interpolate(arg, &field, &lookup)?;
+        }
+        let hooks = [
+            ("pre_start", service.pre_start.as_mut()),
+            ("post_start", service.post_start.as_mut()),
+            ("pre_stop", service.pre_stop.as_mut()),
+            ("post_stop", service.post_stop.as_mut()),
+        ];
+        for (hook_name, hook) in hooks {
+            let Some(hook) = hook else { continue };
+            for (i, arg) in hook.cmd.iter_mut().enumerate() {
+                let field = format!("services.{}.{}.cmd[{}]", name, hook_name, i);
+                *arg = interpolate(arg, &field, &lookup)?;
+            }
+            for (key, value) in hook.environment.iter_mut() {
+                let field = format!("services.{}.{}.environment.{}", name, hook_name, key);
+                *value = interpolate(value, &field, &lookup)?;
+            }
+        }
+        for (check_name, check) in service.health_checks_mut() {
+            let Some(check) = check else { continue };
+            let field = format!("services.{}.{}.test_target", name, check_name);
+            check.test_target = interpolate(&check.test_target, &field, &lookup)?;
+            for (i, arg) in check.test_cmd.iter_mut().enumerate() {
+                let field = format!("services.{}.{}.test_cmd[{}]", name, check_name, i);
+                *arg = interpolate(arg, &field, &lookup)?;
+            }
+            for (key, value) in check.http.headers.iter_mut() {
+                let field = format!("services.{}.{}.http.headers.{}", name, check_name, key);
+                *value = interpolate(value, &field, &lookup)?;
+            }
+        }
+        for (key, value) in service.environment.iter_mut() {
+            let field = format!("services.{}.environment.{}", name, key);
+            *value = interpolate(value, &field, &lookup)?;
+        }
+    }
+    Ok(())
+}
+
+//支持${VAR}、${VAR:-default}(变量未设置或为空时使用默认值)、${VAR:?error}(变量未设置或为空时报错)，
+//$$表示字面量$
+fn interpolate<F>(value: &str, field: &str, lookup: &F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("$$") {
+            result.push('$');
+            rest = &rest[2..];
+            continue;
+        }
+        if !rest.starts_with("${") {
+            result.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+        let end = rest.find('}').ok_or_else(|| {
+            Error::msg(format!(
+                "{}: unclosed variable reference in \"{}\"",
+                field, value
+            ))
+        })?;
+        let expr = &rest[2..end];
+        rest = &rest[end + 1..];
+        //实例序号在创建各个实例时才展开
+        if is_replica_index_expr(expr) {
+            result.push_str("${");
+            result.push_str(expr);
+            result.push('}');
+            continue;
+        }
+        if let Some((name, default)) = expr.split_once(":-") {
+            match lookup(name).filter(|v| !v.is_empty()) {
+                Some(v) => result.push_str(&v),
+                None => result.push_str(default),
+            }
+        } else if let Some((name, message)) = expr.split_once(":?") {
+            match lookup(name).filter(|v| !v.is_empty()) {
+                Some(v) => result.push_str(&v),
+                None => {
+                    let message = if message.is_empty() {
+                        "required variable is not set"
+                    } else {
+                        message
+                    };
+                    return Err(Error::msg(format!("{}: {}: {}", field, name, message)));
+                }
+            }
+        } else {
+            if expr.is_empty() {
+                return Err(Error::msg(format!(
+                    "{}: empty variable reference in \"{}\"",
+                    field, value
+                )));
+            }
+            result.push_str(&lookup(expr).unwrap_or_default());
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+const REPLICA_INDEX_VAR: &str = "REPLICA_INDEX";
+
+fn is_replica_index_expr(expr: &str) -> bool {
+    expr == REPLICA_INDEX_VAR
+        || expr
+            .strip_prefix(REPLICA_INDEX_VAR)
+            .is_some_and(|offset| offset.starts_with('+'))
+}
+
+//展开${REPLICA_INDEX}和${REPLICA_INDEX+N}(如端口偏移)
+fn expand_replica_index(value: &str, field: &str, index: u32) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find("${REPLICA_INDEX") {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let end = match rest.find('}') {
+            Some(end) if is_replica_index_expr(&rest[2..end]) => end,
+            _ => {
+                result.push_str("${");
+                rest = &rest[2..];
+                continue;
+            }
+        };
+        let expr = &rest[2..end];
+        let offset = match expr.strip_prefix("REPLICA_INDEX+") {
+            Some(offset) => offset.trim().parse::<u32>().map_err(|_| {
+                Error::msg(format!(
+                    "{}: invalid replica index offset in \"{}\"",
+                    field, value
+                ))
+            })?,
+            None => 0,
+        };
+        result.push_str(&(index + offset).to_string());
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+//实例的名称，第0个实例沿用服务名
+pub fn instance_name(service_name: &str, index: u32) -> String {
+    if index == 0 {
+        service_name.to_string()
+    } else {
+        format!("{}-{}", service_name, index)
+    }
+}
+
+//生成服务某个实例的配置：名称为实例名，并展开配置中的实例序号
+pub fn instance_config(service: &ServiceConfig, index: u32) -> Result<ServiceConfig> {
+    let mut instance = service.clone();
+    let base = &service.name;
+    instance.name = instance_name(base, index);
+    let expand = |value: &mut String, field: String| -> Result<()> {
+        *value = expand_replica_index(value, &field, index)?;
+        Ok(())
+    };
+    for (i, arg) in instance.start_cmd.iter_mut().enumerate() {
+        expand(arg, format!("services.{}.start_cmd[{}]", base, i))?;
+    }
+    for (i, arg) in instance.stop_cmd.iter_mut().enumerate() {
+        expand(arg, format!("services.{}.stop_cmd[{}]", base, i))?;
+    }
+    if let Some(working_dir) = instance.working_dir.as_mut() {
+        expand(working_dir, format!("services.{}.working_dir", base))?;
+    }
+    for (check_name, check) in instance.health_checks_mut() {
+        let Some(check) = check else { continue };
+        let field = format!("services.{}.{}.test_target", base, check_name);
+        expand(&mut check.test_target, field)?;
+        for (i, arg) in check.test_cmd.iter_mut().enumerate() {
+            let field = format!("services.{}.{}.test_cmd[{}]", base, check_name, i);
+            expand(arg, field)?;
+        }
+    }
+    for (key, value) in instance.environment.iter_mut() {
+        expand(value, format!("services.{}.environment.{}", base, key))?;
+    }
+    let hooks = [
+        ("pre_start", instance.pre_start.as_mut()),
+        ("post_start", instance.post_start.as_mut()),
+        ("pre_stop", instance.pre_stop.as_mut()),
+        ("post_stop", instance.post_stop.as_mut()),
+    ];
+    for (hook_name, hook) in hooks {
+        let Some(hook) = hook else { continue };
+        for (i, arg) in hook.cmd.iter_mut().enumerate() {
+            expand(arg, format!("services.{}.{}.cmd[{}]", base, hook_name, i))?;
+        }
+        for (key, value) in hook.environment.iter_mut() {
+            let field = format!("services.{}.{}.environment.{}", base, hook_name, key);
+            expand(value, field)?;
+        }
+    }
+    //实例序号同时以环境变量的方式提供给服务进程
+    instance
+        .environment
+        .insert(REPLICA_INDEX_VAR.to_string(), index.to_string());
+    Ok(instance)
+}
+
+//服务所有实例的配置
+pub fn instance_configs(service: &ServiceConfig, replicas: u32) -> Result<Vec<ServiceConfig>> {
+    (0..replicas.max(1))
+        .map(|index| instance_config(service, index))
+        .collect()
+}
+
+//替换当前生效的配置，用于热加载及测试
 pub fn set_config(config: GlobalConfig) {
     CONFIG.write().unwrap().replace(config);
 }
@@ -175,7 +1154,7 @@ fn process_independent_service(
     remained.iter().for_each(|service| {
         let mut dep_solved = true;
         let depends = service.depends_on.clone().unwrap();
-        depends.iter().for_each(|dep| {
+        depends.keys().for_each(|dep| {
             if !processed.contains(dep) {
                 dep_solved = false;
             }
@@ -200,14 +1179,493 @@ mod tests {
     fn create_service_config(name: &str, depends_on: Vec<&str>) -> ServiceConfig {
         ServiceConfig {
             name: name.to_string(),
-            depends_on: Some(depends_on.iter().map(|&s| s.to_string()).collect()),
-            log_redirect: false,
-            log_pattern: None,
-            healthcheck: None,
+            depends_on: Some(
+                depends_on
+                    .iter()
+                    .map(|&s| (s.to_string(), DependsOnConfig::default()))
+                    .collect(),
+            ),
             start_cmd: vec!["".to_owned()],
+            ..Default::default()
         }
     }
 
+    #[test]
+    fn test_instance_config_expands_replica_index() {
+        let yaml = r#"
+start_cmd: ["app", "--port", "${REPLICA_INDEX+8080}", "--id=${REPLICA_INDEX}"]
+log_redirect: false
+environment:
+  DATA_DIR: /data/${REPLICA_INDEX}
+healthcheck:
+  test_type: http
+  test_target: http://127.0.0.1:${REPLICA_INDEX+8080}/health
+replicas: 3
+"#;
+        let mut service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        service.name = "web".to_string();
+        //加载配置时的变量替换不处理实例序号
+        let r = interpolate(&service.start_cmd[2], "f", &lookup).unwrap();
+        assert_eq!(r, "${REPLICA_INDEX+8080}");
+        let instances = instance_configs(&service, service.replicas).unwrap();
+        let names: Vec<_> = instances.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["web", "web-1", "web-2"]);
+        assert_eq!(
+            instances[2].start_cmd,
+            vec!["app", "--port", "8082", "--id=2"]
+        );
+        assert_eq!(instances[1].environment["DATA_DIR"], "/data/1");
+        assert_eq!(instances[1].environment["REPLICA_INDEX"], "1");
+        assert_eq!(
+            instances[1].healthcheck.as_ref().unwrap().test_target,
+            "http://127.0.0.1:8081/health"
+        );
+        service.start_cmd = vec!["app".to_string(), "${REPLICA_INDEX+x}".to_string()];
+        let err = instance_config(&service, 1).unwrap_err();
+        assert!(err.to_string().contains("services.web.start_cmd[1]"));
+    }
+
+    #[test]
+    fn test_default_replicas() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(service.replicas, 1);
+        assert_eq!(ServiceConfig::default().replicas, 1);
+    }
+
+    #[test]
+    fn test_deserialize_environment_map_and_list() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+environment:
+  PORT: 8080
+  DEBUG: true
+  NAME: demo
+env_file: .env
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(service.environment.get("PORT").unwrap(), "8080");
+        assert_eq!(service.environment.get("DEBUG").unwrap(), "true");
+        assert_eq!(service.environment.get("NAME").unwrap(), "demo");
+        assert_eq!(service.env_file, vec![".env"]);
+
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+environment:
+  - PORT=8080
+  - OPTS=-Xmx1g -Da=b
+env_file: [a.env, b.env]
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(service.environment.get("PORT").unwrap(), "8080");
+        assert_eq!(service.environment.get("OPTS").unwrap(), "-Xmx1g -Da=b");
+        assert_eq!(service.env_file, vec!["a.env", "b.env"]);
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("10.0.0.1".to_string()),
+            "PORT" => Some("8080".to_string()),
+            "EMPTY" => Some("".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let r = interpolate("http://${HOST}:${PORT}/health", "f", &lookup).unwrap();
+        assert_eq!(r, "http://10.0.0.1:8080/health");
+        let r = interpolate("${MISSING:-9090} ${EMPTY:-x} ${PORT:-1}", "f", &lookup).unwrap();
+        assert_eq!(r, "9090 x 8080");
+        let r = interpolate("$$HOME ${MISSING} $PORT", "f", &lookup).unwrap();
+        assert_eq!(r, "$HOME  $PORT");
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        let err = interpolate(
+            "${MISSING:?port is required}",
+            "services.web.start_cmd[1]",
+            &lookup,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "services.web.start_cmd[1]: MISSING: port is required"
+        );
+        assert!(interpolate("${EMPTY:?}", "f", &lookup).is_err());
+        assert!(interpolate("${HOST", "f", &lookup).is_err());
+        assert!(interpolate("${}", "f", &lookup).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_config_fields() {
+        let yaml = r#"
+app_data_home: /data/${PC_TEST_MISSING:-home}
+services:
+  web:
+    log_redirect: false
+    start_cmd: ["./app", "--port=${PC_TEST_PORT}"]
+    healthcheck:
+      test_type: http
+      test_target: http://${PC_TEST_HOST}:${PC_TEST_PORT}/
+    environment:
+      UPSTREAM: ${PC_TEST_HOST}
+"#;
+        //使用测试专用的变量名，避免受到运行环境中同名环境变量的影响
+        let mut config: GlobalConfig = serde_yaml::from_str(yaml).unwrap();
+        let dot_env = HashMap::from([
+            ("PC_TEST_HOST".to_string(), "127.0.0.1".to_string()),
+            ("PC_TEST_PORT".to_string(), "18080".to_string()),
+        ]);
+        interpolate_config(&mut config, &dot_env).unwrap();
+        let web = config.services.get("web").unwrap();
+        assert_eq!(config.app_data_home, "/data/home");
+        assert_eq!(web.start_cmd[1], "--port=18080");
+        assert_eq!(
+            web.healthcheck.as_ref().unwrap().test_target,
+            "http://127.0.0.1:18080/"
+        );
+        assert_eq!(web.environment.get("UPSTREAM").unwrap(), "127.0.0.1");
+    }
+
+    #[test]
+    fn test_deserialize_depends_on() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+depends_on: [db, cache]
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        let depends_on = service.depends_on.unwrap();
+        assert_eq!(depends_on.len(), 2);
+        assert_eq!(
+            depends_on["db"].condition,
+            DependsOnCondition::ServiceHealthy
+        );
+
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+depends_on:
+  db:
+    condition: service_started
+  migrate:
+    condition: service_completed_successfully
+  cache:
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        let depends_on = service.depends_on.unwrap();
+        assert_eq!(
+            depends_on["db"].condition,
+            DependsOnCondition::ServiceStarted
+        );
+        assert_eq!(
+            depends_on["migrate"].condition,
+            DependsOnCondition::ServiceCompletedSuccessfully
+        );
+        assert_eq!(
+            depends_on["cache"].condition,
+            DependsOnCondition::ServiceHealthy
+        );
+
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(service.depends_on.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_stop_options() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(service.stop_signal, StopSignal::Term);
+        assert_eq!(service.stop_timeout, 10);
+        assert!(service.stop_cmd.is_empty());
+
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+stop_signal: SIGQUIT
+stop_timeout: 60
+stop_cmd: ["curl", "-X", "POST", "http://localhost:8080/actuator/shutdown"]
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(service.stop_signal, StopSignal::Quit);
+        assert_eq!(service.stop_signal.to_string(), "SIGQUIT");
+        assert_eq!(service.stop_timeout, 60);
+        assert_eq!(service.stop_cmd.len(), 4);
+    }
+
+    #[test]
+    fn test_deserialize_hooks() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+pre_start:
+  cmd: ["mkdir", "-p", "data"]
+post_stop:
+  cmd: ["rm", "-f", "app.lock"]
+  timeout: 5
+  environment:
+    LOCK: app.lock
+  on_failure: ignore
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        let pre_start = service.pre_start.unwrap();
+        assert_eq!(pre_start.timeout, 30);
+        assert_eq!(pre_start.on_failure, HookFailurePolicy::Abort);
+        let post_stop = service.post_stop.unwrap();
+        assert_eq!(post_stop.timeout, 5);
+        assert_eq!(post_stop.on_failure, HookFailurePolicy::Ignore);
+        assert_eq!(post_stop.environment.get("LOCK").unwrap(), "app.lock");
+        assert!(service.post_start.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_http_check() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+healthcheck:
+  test_type: http
+  test_target: https://localhost:8443/health
+  retries: 3
+  http:
+    method: HEAD
+    expected_status: [200, "301-302", "4xx"]
+    json_match:
+      pointer: /status
+      value: UP
+    max_redirects: 0
+    insecure: true
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        let healthcheck = service.healthcheck.unwrap();
+        assert_eq!(healthcheck.max_failures, 3);
+        let http = healthcheck.http;
+        assert_eq!(http.method, "HEAD");
+        assert_eq!(
+            http.expected_status,
+            vec![
+                StatusRange {
+                    start: 200,
+                    end: 200
+                },
+                StatusRange {
+                    start: 301,
+                    end: 302
+                },
+                StatusRange {
+                    start: 400,
+                    end: 499
+                },
+            ]
+        );
+        assert_eq!(http.json_match.unwrap().value, serde_json::json!("UP"));
+        assert!(http.insecure);
+        assert!(StatusRange::from_str("302-301").is_err());
+        assert!(StatusRange::from_str("9xx").is_err());
+        let yaml = yaml.replace("[200, ", "[2000, ");
+        assert!(serde_yaml::from_str::<ServiceConfig>(&yaml).is_err());
+    }
+
+    #[test]
+    fn test_readiness_and_liveness() {
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+healthcheck:
+  test_type: tcp
+  test_target: 127.0.0.1:8080
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        //只配置healthcheck时同时作为就绪检查及存活检查
+        assert_eq!(service.readiness_check(), service.healthcheck.as_ref());
+        assert_eq!(service.liveness_check(), service.healthcheck.as_ref());
+        let yaml = r#"
+start_cmd: ["app"]
+log_redirect: false
+readiness:
+  test_type: log
+  test_target: started
+liveness:
+  test_type: process
+"#;
+        let service: ServiceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            service.readiness_check().unwrap().test_type,
+            HealthCheckType::Log
+        );
+        assert_eq!(
+            service.liveness_check().unwrap().test_type,
+            HealthCheckType::Proccess
+        );
+        let mut service = ServiceConfig {
+            name: "web".to_string(),
+            ..service
+        };
+        service.liveness = service.readiness.clone();
+        let config = GlobalConfig {
+            log_level: "info".to_string(),
+            app_data_home: "".to_string(),
+            sys_service_name: "".to_string(),
+            sys_service_desc: "".to_string(),
+            services: HashMap::from([("web".to_string(), service)]),
+            api: None,
+        };
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("services.web.liveness.test_type"));
+    }
+
+    #[test]
+    fn test_diff_services() {
+        let mut old = HashMap::new();
+        old.insert("a".to_string(), create_service_config("a", vec![]));
+        old.insert("b".to_string(), create_service_config("b", vec![]));
+        old.insert("c".to_string(), create_service_config("c", vec![]));
+        let mut new = old.clone();
+        new.remove("a");
+        new.get_mut("b").unwrap().start_cmd = vec!["./b".to_string()];
+        new.insert("d".to_string(), create_service_config("d", vec!["c"]));
+        let diff = diff_services(&old, &new);
+        assert_eq!(
+            diff,
+            ServicesDiff {
+                added: vec!["d".to_string()],
+                removed: vec!["a".to_string()],
+                changed: vec!["b".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_config_rejects_unknown_dependency() {
+        let mut services = HashMap::new();
+        services.insert("a".to_string(), create_service_config("a", vec!["x"]));
+        let config = GlobalConfig {
+            log_level: "info".to_string(),
+            app_data_home: "".to_string(),
+            sys_service_name: "".to_string(),
+            sys_service_desc: "".to_string(),
+            services,
+            api: None,
+        };
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("service x is not defined"));
+        let config = GlobalConfig {
+            log_level: "verbose".to_string(),
+            services: HashMap::new(),
+            ..config
+        };
+        assert!(validate_config(&config).is_err());
+
+        let mut job = create_service_config("job", vec![]);
+        job.schedule = Some(ScheduleConfig {
+            cron: "0 0 2 * *".to_string(),
+            timezone: None,
+            overlap: OverlapPolicy::Skip,
+            max_runtime: None,
+        });
+        let config = GlobalConfig {
+            log_level: "info".to_string(),
+            services: HashMap::from([("job".to_string(), job.clone())]),
+            ..config
+        };
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("services.job.schedule.cron"));
+        let mut web = create_service_config("web", vec![]);
+        web.log_pattern = Some("{d} {level} {m}".to_string());
+        let pattern_config = GlobalConfig {
+            services: HashMap::from([("web".to_string(), web)]),
+            ..config.clone()
+        };
+        let err = validate_config(&pattern_config).unwrap_err();
+        assert!(err.to_string().contains("services.web.log_pattern"));
+        let mut web = create_service_config("web", vec![]);
+        web.healthcheck = Some(HealthCheckConfig {
+            test_type: HealthCheckType::Log,
+            test_target: "listening on (".to_string(),
+            interval: 1,
+            max_failures: 1,
+            success_threshold: 1,
+            start_period: None,
+            test_cmd: Vec::new(),
+            timeout: None,
+            log_timeout: Some(10),
+            http: HttpCheckConfig::default(),
+        });
+        let log_config = GlobalConfig {
+            services: HashMap::from([("web".to_string(), web.clone())]),
+            ..config.clone()
+        };
+        let err = validate_config(&log_config).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("services.web.healthcheck.test_target"));
+        //log类型使用log_timeout，timeout不能用于log类型
+        let healthcheck = web.healthcheck.as_mut().unwrap();
+        healthcheck.test_target = "listening".to_string();
+        healthcheck.timeout = Some(5);
+        let log_timeout_config = GlobalConfig {
+            services: HashMap::from([("web".to_string(), web)]),
+            ..config.clone()
+        };
+        let err = validate_config(&log_timeout_config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "services.web.healthcheck.timeout is not supported by log checks, use log_timeout instead"
+        );
+        //定时服务不能运行多个实例
+        let mut replicated_job = create_service_config("job", vec![]);
+        replicated_job.replicas = 2;
+        replicated_job.schedule = Some(ScheduleConfig {
+            cron: "0 0 2 * * *".to_string(),
+            timezone: None,
+            overlap: OverlapPolicy::Skip,
+            max_runtime: None,
+        });
+        let replicated_config = GlobalConfig {
+            services: HashMap::from([("job".to_string(), replicated_job)]),
+            ..config.clone()
+        };
+        assert!(validate_config(&replicated_config).is_err());
+        //实例名不能与其它服务重名
+        let mut web = create_service_config("web", vec![]);
+        web.replicas = 2;
+        let replicated_config = GlobalConfig {
+            services: HashMap::from([
+                ("web".to_string(), web),
+                ("web-1".to_string(), create_service_config("web-1", vec![])),
+            ]),
+            ..config.clone()
+        };
+        let err = validate_config(&replicated_config).unwrap_err();
+        assert!(err.to_string().contains("instance name web-1"));
+        job.schedule = Some(ScheduleConfig {
+            cron: "0 0 2 * * *".to_string(),
+            timezone: Some("Mars/Olympus".to_string()),
+            overlap: OverlapPolicy::Skip,
+            max_runtime: None,
+        });
+        let config = GlobalConfig {
+            services: HashMap::from([("job".to_string(), job)]),
+            ..config
+        };
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("services.job.schedule.timezone"));
+    }
+
     #[test]
     fn test_analyze_service_dependencies_no_dependencies() {
         let services = vec![
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Mutex, RwLock,
+    },
+    thread,
+};
+
+use lazy_static::lazy_static;
+
+use crate::config::{self, instance_name};
+
+const COLORS: [&str; 6] = [
+    "\x1b[36m", "\x1b[33m", "\x1b[32m", "\x1b[35m", "\x1b[34m", "\x1b[31m",
+];
+const RESET: &str = "\x1b[0m";
+//终端输出的缓存上限，终端被暂停或输出缓慢时丢弃新的行，不影响服务日志的写入
+const CONSOLE_BUFFER_SIZE: usize = 1024;
+
+//up模式下需要输出到终端的服务
+#[derive(Clone, Debug, Default)]
+struct ConsoleOptions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    //标准输出不是终端时不使用颜色
+    color: bool,
+}
+
+lazy_static! {
+    static ref CONSOLE: RwLock<Option<ConsoleOptions>> = RwLock::new(None);
+    //为每个实例分配的颜色，服务重启后保持不变
+    static ref INSTANCE_COLORS: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+    //所有服务的终端输出由单独的线程写入，终端阻塞时不会影响服务日志文件的写入
+    static ref CONSOLE_SENDER: SyncSender<String> = start_console_writer();
+}
+
+//终端跟不上时被丢弃的行数
+static DROPPED_LINES: AtomicU64 = AtomicU64::new(0);
+
+//服务名前缀的宽度，按最长的实例名对齐
+static PREFIX_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+//开启up模式：各服务的标准输出及错误输出都会以服务名为前缀输出到终端
+pub fn enable(include: Vec<String>, exclude: Vec<String>) {
+    let width = config::current_config()
+        .services
+        .values()
+        .map(|s| instance_name(&s.name, s.replicas.max(1) - 1).len())
+        .max()
+        .unwrap_or(0);
+    PREFIX_WIDTH.fetch_max(width, Ordering::Relaxed);
+    CONSOLE.write().unwrap().replace(ConsoleOptions {
+        include,
+        exclude,
+        color: io::stdout().is_terminal(),
+    });
+}
+
+fn start_console_writer() -> SyncSender<String> {
+    let (sender, receiver) = mpsc::sync_channel::<String>(CONSOLE_BUFFER_SIZE);
+    thread::spawn(move || {
+        for line in receiver {
+            //终端输出失败时忽略
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(line.as_bytes());
+            let dropped = DROPPED_LINES.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                let _ = writeln!(
+                    stdout,
+                    "[process-compose] {} console lines dropped",
+                    dropped
+                );
+            }
+        }
+    });
+    sender
+}
+
+fn is_selected(options: &ConsoleOptions, instance: &str, service: &str) -> bool {
+    let matches = |names: &Vec<String>| names.iter().any(|n| n == instance || n == service);
+    (options.include.is_empty() || matches(&options.include)) && !matches(&options.exclude)
+}
+
+//输出服务的一行内容到终端
+pub(crate) struct ConsolePrinter {
+    name: String,
+    color: Option<&'static str>,
+}
+
+//未开启up模式或服务被排除时返回None
+pub(crate) fn printer(instance: &str, service: &str) -> Option<ConsolePrinter> {
+    let options = CONSOLE.read().unwrap().clone()?;
+    if !is_selected(&options, instance, service) {
+        return None;
+    }
+    PREFIX_WIDTH.fetch_max(instance.len(), Ordering::Relaxed);
+    let color = if options.color {
+        let mut colors = INSTANCE_COLORS.lock().unwrap();
+        let next = colors.len();
+        let index = *colors.entry(instance.to_string()).or_insert(next);
+        Some(COLORS[index % COLORS.len()])
+    } else {
+        None
+    };
+    Some(ConsolePrinter {
+        name: instance.to_string(),
+        color,
+    })
+}
+
+impl ConsolePrinter {
+    pub(crate) fn print(&self, text: &str) {
+        let width = PREFIX_WIDTH.load(Ordering::Relaxed);
+        let line = match self.color {
+            Some(color) => format!("{}{:<width$} |{} {}\n", color, self.name, RESET, text),
+            None => format!("{:<width$} | {}\n", self.name, text),
+        };
+        if let Err(TrySendError::Full(_)) = CONSOLE_SENDER.try_send(line) {
+            DROPPED_LINES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_and_exclude() {
+        let options = |include: Vec<&str>, exclude: Vec<&str>| ConsoleOptions {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            color: false,
+        };
+        let all = options(vec![], vec![]);
+        assert!(is_selected(&all, "web-1", "web"));
+        //服务名对其所有实例生效
+        let only_web = options(vec!["web"], vec![]);
+        assert!(is_selected(&only_web, "web-1", "web"));
+        assert!(!is_selected(&only_web, "db", "db"));
+        let without_instance = options(vec!["web"], vec!["web-1"]);
+        assert!(is_selected(&without_instance, "web", "web"));
+        assert!(!is_selected(&without_instance, "web-1", "web"));
+    }
+}
@@ -1,9 +1,11 @@
 use std::{
-    fs::{self, File},
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{Error, Result};
 use chrono::Utc;
 use clap::Parser;
 use lazy_static::lazy_static;
@@ -35,12 +37,16 @@ pub fn create_services_home(services: &Vec<ServiceConfig>) -> Result<()> {
     Ok(())
 }
 
-pub fn create_service_redirect_log_file(svc_name: &str, file_prefix: &str) -> Result<File> {
+//以追加的方式打开服务日志目录下的日志文件，用于钩子命令等多次执行的输出
+pub fn open_service_append_log_file(svc_name: &str, file_prefix: &str) -> Result<File> {
     let dir = get_service_log_dir(svc_name);
     fs::create_dir_all(&dir)?;
     let today = Utc::now().format("%Y%m%d").to_string();
-    let file_path = format!("{}/{}_{}.log", dir.to_string_lossy(), file_prefix, today);
-    let file = File::create(&file_path)?;
+    let file_path = dir.join(format!("{}_{}.log", file_prefix, today));
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
     Ok(file)
 }
 
@@ -49,7 +55,104 @@ pub fn get_service_home(service_name: &str) -> PathBuf {
     Path::new(&config.app_data_home).join(service_name)
 }
 
-fn get_service_log_dir(svc_name: &str) -> PathBuf {
+//服务的工作目录，服务的相对路径均基于该目录
+pub fn get_service_work_dir(service: &ServiceConfig) -> PathBuf {
+    match &service.working_dir {
+        Some(dir) => ROOT_DIR.join(dir),
+        //未配置工作目录且服务目录不存在时(如直接使用全局命令的服务)，使用配置文件所在目录
+        None => {
+            let dir = ROOT_DIR.join(&service.name);
+            if dir.is_dir() {
+                dir
+            } else {
+                ROOT_DIR.clone()
+            }
+        }
+    }
+}
+
+//解析服务的启动命令：绝对路径直接使用，带路径分隔符的相对路径基于工作目录，
+//单独的命令名则从PATH中查找
+pub fn resolve_command(
+    command: &str,
+    work_dir: &Path,
+    path_env: Option<&String>,
+) -> Result<PathBuf> {
+    let command_path = Path::new(command);
+    if command_path.is_absolute() {
+        return Ok(command_path.to_path_buf());
+    }
+    if command_path.components().count() > 1 || command.starts_with('.') {
+        return Ok(work_dir.join(command_path));
+    }
+    let path_env = path_env
+        .map(OsString::from)
+        .or_else(|| std::env::var_os("PATH"))
+        .unwrap_or_default();
+    for dir in std::env::split_paths(&path_env) {
+        for candidate in executable_candidates(&dir.join(command)) {
+            if is_executable(&candidate) {
+                return Ok(candidate);
+            }
+        }
+    }
+    Err(Error::msg(format!(
+        "command {} not found in PATH: {}",
+        command,
+        path_env.to_string_lossy()
+    )))
+}
+
+#[cfg(target_os = "windows")]
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![path.to_path_buf()];
+    if path.extension().is_none() {
+        for ext in ["exe", "bat", "cmd", "com"] {
+            candidates.push(path.with_extension(ext));
+        }
+    }
+    candidates
+}
+
+#[cfg(not(target_os = "windows"))]
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    vec![path.to_path_buf()]
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+//计算需要额外设置给服务进程的环境变量，优先级从低到高为：
+//process-compose自身的环境变量 < env_file(按配置顺序，后面的覆盖前面的) < environment
+pub fn resolve_service_environment(service: &ServiceConfig) -> Result<HashMap<String, String>> {
+    let work_dir = get_service_work_dir(service);
+    let mut envs = HashMap::new();
+    for env_file in service.env_file.iter() {
+        let path = work_dir.join(env_file);
+        let items = dotenvy::from_path_iter(&path)
+            .map_err(|e| Error::msg(format!("load env_file {} failed: {}", path.display(), e)))?;
+        for item in items {
+            let (key, value) = item.map_err(|e| {
+                Error::msg(format!("parse env_file {} failed: {}", path.display(), e))
+            })?;
+            envs.insert(key, value);
+        }
+    }
+    envs.extend(service.environment.clone());
+    Ok(envs)
+}
+
+pub fn get_service_log_dir(svc_name: &str) -> PathBuf {
     get_service_home(svc_name).join("logs")
 }
 
@@ -60,10 +163,88 @@ fn get_service_data_dir(svc_name: &str) -> PathBuf {
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-    /// service action, support: start, stop, install, uninstall
+    /// service action, support: up, start, stop, install, uninstall, ps, reload;
+    /// with a service name: ps, start, stop, restart, reset, health, scale
     pub service_action: Option<String>,
 
+    /// managed service name, the action will be sent to the running process-compose
+    pub service_name: Option<String>,
+
+    /// extra arguments of the action, e.g. the replica count of scale
+    pub action_args: Vec<String>,
+
+    /// up: only print the output of these services (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub include: Vec<String>,
+
+    /// up: don't print the output of these services (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
     /// internal arg,don't use it
     #[arg(long, default_value_t = false)]
     pub run_as_service: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_service_environment_precedence() {
+        let dir = std::env::temp_dir().join("process-compose-env-test");
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.env");
+        let second = dir.join("second.env");
+        fs::write(&first, "A=first\nB=first\nC=first\n").unwrap();
+        fs::write(&second, "B=second\nC=second\n").unwrap();
+        let service = ServiceConfig {
+            name: "service1".to_string(),
+            env_file: vec![
+                first.to_string_lossy().to_string(),
+                second.to_string_lossy().to_string(),
+            ],
+            environment: HashMap::from([("C".to_string(), "environment".to_string())]),
+            ..Default::default()
+        };
+        let envs = resolve_service_environment(&service).unwrap();
+        assert_eq!(envs.get("A").unwrap(), "first");
+        assert_eq!(envs.get("B").unwrap(), "second");
+        assert_eq!(envs.get("C").unwrap(), "environment");
+    }
+
+    #[test]
+    fn test_resolve_relative_command() {
+        let work_dir = Path::new("/opt/app/service1");
+        let resolve = |cmd: &str| resolve_command(cmd, work_dir, None).unwrap();
+        assert_eq!(resolve("./bin/app"), work_dir.join("./bin/app"));
+        assert_eq!(resolve("../bin/app"), work_dir.join("../bin/app"));
+        assert_eq!(resolve("bin/app"), work_dir.join("bin/app"));
+        assert_eq!(resolve(".app"), work_dir.join(".app"));
+        assert_eq!(resolve("/usr/bin/env"), PathBuf::from("/usr/bin/env"));
+    }
+
+    #[test]
+    fn test_resolve_command_from_path() {
+        let dir = std::env::temp_dir().join("process-compose-path-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path_env = format!("/not/exists:{}:/bin", dir.to_string_lossy());
+        assert_eq!(
+            resolve_command("sh", Path::new("."), Some(&path_env)).unwrap(),
+            PathBuf::from("/bin/sh")
+        );
+        let err = resolve_command("no-such-command", Path::new("."), Some(&path_env)).unwrap_err();
+        assert!(err.to_string().contains("no-such-command"));
+    }
+
+    #[test]
+    fn test_resolve_service_environment_missing_file() {
+        let service = ServiceConfig {
+            name: "service1".to_string(),
+            env_file: vec!["/not/exists/.env".to_string()],
+            ..Default::default()
+        };
+        let err = resolve_service_environment(&service).unwrap_err();
+        assert!(err.to_string().contains("/not/exists/.env"));
+    }
+}
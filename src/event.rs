@@ -5,7 +5,7 @@ use std::sync::{
 
 use log::{debug, error, info, warn};
 
-use crate::{config, health, process};
+use crate::{health, process};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum EventType {
@@ -19,6 +19,10 @@ pub enum EventType {
     Unhealthy = 4,
     //健康检查通过
     Healthy = 5,
+    //服务连续崩溃超过限制，不再自动重启
+    Failed = 6,
+    //一次性任务成功运行结束
+    Completed = 7,
 }
 
 pub struct ProcessEvent {
@@ -76,8 +80,13 @@ pub fn handle_process_event(sender: Sender<ProcessEvent>, rx: Receiver<ProcessEv
                         .pid
                         .map_or_else(|| "unknown".to_string(), |pid| pid.to_string())
                 );
-                let service_cfg = config::find_service_config(&received.service_name);
-                health::start_watch(received.service_name, service_cfg.unwrap().healthcheck);
+                //使用实例自身的配置，其中的实例序号已展开
+                let proc_runtime =
+                    process::status::find_readonly_proc_runtime(&received.service_name);
+                if let Ok(proc_runtime) = proc_runtime {
+                    health::start_watch(received.service_name, &proc_runtime.config);
+                }
+                process::pending::try_start_pending_service();
             }
             EventType::Exited => {
                 let pid = received
@@ -88,36 +97,67 @@ pub fn handle_process_event(sender: Sender<ProcessEvent>, rx: Receiver<ProcessEv
                     "[{}] (pid: {}) has exited:{}",
                     received.service_name, pid, msg
                 );
+                process::schedule::on_run_finished(&received.service_name);
+                //已安排自动重启的服务暂停健康检查，避免健康检查同时触发重启
+                if process::restart::on_service_exited(&received.service_name) {
+                    health::stop_watch(received.service_name);
+                }
+                //依赖条件可能是依赖服务成功运行结束
+                process::pending::try_start_pending_service();
             }
             EventType::Stopped => {
                 let pid = received
                     .pid
                     .map_or_else(|| "unknown".to_string(), |pid| pid.to_string());
+                let msg = received.data.unwrap_or_else(|| "unknown".to_string());
                 info!(
-                    "[{}] (pid: {}) has be stopped,will stop health watch",
-                    received.service_name, pid,
+                    "[{}] (pid: {}) has be stopped ({}),will stop health watch",
+                    received.service_name, pid, msg
                 );
+                process::schedule::on_run_finished(&received.service_name);
                 health::stop_watch(received.service_name)
             }
             EventType::Unhealthy => {
-                process::status::change_proc_health_status(&received.service_name, false)
-                    .unwrap_or_else(|err| {
-                        warn!(
-                            "change [{}] health status failed: {}",
-                            &received.service_name, err
-                        );
-                    });
+                process::status::change_proc_health_status(
+                    &received.service_name,
+                    false,
+                    received.data,
+                )
+                .unwrap_or_else(|err| {
+                    warn!(
+                        "change [{}] health status failed: {}",
+                        &received.service_name, err
+                    );
+                });
             }
             EventType::Healthy => {
-                process::status::change_proc_health_status(&received.service_name, true)
-                    .unwrap_or_else(|err| {
-                        warn!(
-                            "change [{}] health status failed: {}",
-                            &received.service_name, err
-                        );
-                    });
+                process::status::change_proc_health_status(
+                    &received.service_name,
+                    true,
+                    received.data,
+                )
+                .unwrap_or_else(|err| {
+                    warn!(
+                        "change [{}] health status failed: {}",
+                        &received.service_name, err
+                    );
+                });
+                process::pending::try_start_pending_service();
+            }
+            EventType::Completed => {
+                info!("[{}] has completed successfully", received.service_name);
+                process::schedule::on_run_finished(&received.service_name);
+                health::stop_watch(received.service_name);
                 process::pending::try_start_pending_service();
             }
+            EventType::Failed => {
+                let msg = received.data.unwrap_or_else(|| "unknown".to_string());
+                error!(
+                    "[{}] has failed and will stay down until it is reset: {}",
+                    received.service_name, msg
+                );
+                health::stop_watch(received.service_name)
+            }
         }
     }
     error!("event handler has been stoped!!!!!!")
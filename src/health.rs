@@ -1,28 +1,38 @@
 use crate::{
-    config::HealthCheckConfig,
+    config::{HealthCheckConfig, HttpCheckConfig, ServiceConfig},
     event::{self, EventType},
-    process,
+    process::{self, exec, exec::TimedOut},
 };
 use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
 use log::{info, warn};
+use regex::Regex;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    redirect, Method,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    io,
-    net::{TcpStream, ToSocketAddrs},
-    process::Command,
+    fmt, io,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
     str::FromStr,
-    sync::RwLock,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, RwLock,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum HealthCheckType {
     Http,
     Tcp,
     Cmd,
+    //在服务输出中匹配到指定的日志即视为健康
+    Log,
     Proccess,
 }
 
@@ -34,192 +44,645 @@ impl FromStr for HealthCheckType {
             "http" => Ok(HealthCheckType::Http),
             "tcp" => Ok(HealthCheckType::Tcp),
             "cmd" => Ok(HealthCheckType::Cmd),
+            "log" => Ok(HealthCheckType::Log),
             _ => Ok(HealthCheckType::Proccess),
         }
     }
 }
 
+//未配置timeout时单次检查的超时时间
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+//cmd方式检查命令的标准输出及错误输出各自最多保留的字节数
+const MAX_CHECK_OUTPUT: usize = 1024;
+
 lazy_static! {
-    static ref SERVICES_HEALTH_STATUS: RwLock<HashMap<String, i32>> = RwLock::new(HashMap::new());
+    //每次开始检查时分配新的编号，服务重启或配置变更后旧的检查线程据此退出
+    static ref SERVICES_WATCHER_ID: RwLock<HashMap<String, u64>> = RwLock::new(HashMap::new());
 }
 
-pub fn start_watch(service_name: String, config: Option<HealthCheckConfig>) {
-    let health_cfg = config.clone(); // to avoid borrow count
-    if health_cfg.is_none() {
+static WATCHER_SEQ: AtomicU64 = AtomicU64::new(0);
+
+//检查的作用：就绪检查决定服务的健康状态，存活检查失败次数过多时重启服务
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProbeRole {
+    Readiness,
+    Liveness,
+    //healthcheck同时作为就绪检查及存活检查
+    Both,
+}
+
+impl ProbeRole {
+    fn is_readiness(self) -> bool {
+        self != ProbeRole::Liveness
+    }
+
+    fn is_liveness(self) -> bool {
+        self != ProbeRole::Readiness
+    }
+}
+
+impl fmt::Display for ProbeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeRole::Readiness => write!(f, "readiness check"),
+            ProbeRole::Liveness => write!(f, "liveness check"),
+            ProbeRole::Both => write!(f, "health check"),
+        }
+    }
+}
+
+pub fn start_watch(service_name: String, config: &ServiceConfig) {
+    let readiness = config.readiness_check().cloned();
+    let liveness = config.liveness_check().cloned();
+    if readiness.is_none() && liveness.is_none() {
         info!("[{}] is not enabled to health check", &service_name);
         return;
     }
     if is_watching(&service_name) {
         return;
     }
-    set_watch_flag(&service_name);
-    thread::spawn(move || do_watch_health(service_name, config.unwrap()));
-    return;
+    //同一服务的各项检查使用相同的编号，停止检查时一起退出
+    let watcher_id = set_watch_flag(&service_name);
+    let mut probes = Vec::new();
+    match (readiness, liveness) {
+        (Some(readiness), Some(liveness)) if readiness == liveness => {
+            probes.push((readiness, ProbeRole::Both))
+        }
+        (readiness, liveness) => {
+            probes.extend(readiness.map(|c| (c, ProbeRole::Readiness)));
+            probes.extend(liveness.map(|c| (c, ProbeRole::Liveness)));
+        }
+    }
+    for (config, role) in probes {
+        let service_name = service_name.clone();
+        thread::spawn(move || do_watch_health(service_name, config, role, watcher_id));
+    }
 }
 
 pub fn stop_watch(service_name: String) {
-    let mut status = SERVICES_HEALTH_STATUS.write().unwrap();
-    if !status.contains_key(&service_name) {
+    let mut watchers = SERVICES_WATCHER_ID.write().unwrap();
+    if watchers.remove(&service_name).is_none() {
         warn!("[{}] is not being watched, ignore stop", &service_name);
-        return;
     }
-    status.remove(&service_name);
 }
 
 fn is_watching(service_name: &str) -> bool {
-    let status = SERVICES_HEALTH_STATUS.read().unwrap();
-    status.contains_key(service_name)
+    let watchers = SERVICES_WATCHER_ID.read().unwrap();
+    watchers.contains_key(service_name)
 }
 
-fn set_watch_flag(service_name: &str) {
-    let mut status = SERVICES_HEALTH_STATUS.write().unwrap();
-    status.insert(service_name.to_owned(), 0);
+fn is_current_watcher(service_name: &str, watcher_id: u64) -> bool {
+    let watchers = SERVICES_WATCHER_ID.read().unwrap();
+    watchers.get(service_name) == Some(&watcher_id)
 }
 
-fn do_watch_health(service_name: String, config: HealthCheckConfig) {
+fn set_watch_flag(service_name: &str) -> u64 {
+    let watcher_id = WATCHER_SEQ.fetch_add(1, Ordering::SeqCst);
+    let mut watchers = SERVICES_WATCHER_ID.write().unwrap();
+    watchers.insert(service_name.to_owned(), watcher_id);
+    watcher_id
+}
+
+fn do_watch_health(
+    service_name: String,
+    config: HealthCheckConfig,
+    role: ProbeRole,
+    watcher_id: u64,
+) {
+    if config.test_type == HealthCheckType::Log {
+        return watch_log_health(service_name, config, watcher_id);
+    }
     if config.start_period.is_some() {
         thread::sleep(Duration::from_secs(config.start_period.unwrap() as u64));
     }
-    if !is_watching(&service_name) {
+    if !is_current_watcher(&service_name, watcher_id) {
         return;
     }
-    info!("[{}] has enabled health checks", &service_name);
+    //http方式的检查在整个watcher中复用同一个client
+    let http_probe = match config.test_type {
+        HealthCheckType::Http => match HttpProbe::new(&config.http) {
+            Ok(probe) => Some(probe),
+            Err(err) => {
+                warn!("[{}] can not create http {}: {}", service_name, role, err);
+                return;
+            }
+        },
+        _ => None,
+    };
+    info!("[{}] has enabled {}", &service_name, role);
     loop {
-        if !is_watching(&service_name) {
-            info!(
-                "[{}] is not being watched, stop health check",
-                &service_name
-            );
+        if !is_current_watcher(&service_name, watcher_id) {
+            info!("[{}] is not being watched, stop {}", &service_name, role);
             break;
         }
-        let r = check(&service_name, &config);
         let mut check_interval = config.interval;
-        if r.is_err() {
+        //检查超时或出错(如连接被拒绝)都按失败处理，错误信息作为失败的原因
+        let result =
+            check(&service_name, &config, http_probe.as_ref()).unwrap_or_else(|err| CheckResult {
+                success: false,
+                message: Some(err.to_string()),
+            });
+        if !result.success {
+            if let Some(message) = result.message.as_ref() {
+                warn!("[{}] {} failed: {}", service_name, role, message);
+            }
+        }
+        if role.is_readiness() {
+            on_readiness_result(&service_name, &config, result.success, result.message);
+        }
+        //只有存活检查连续失败超过max_failures次才会重启服务
+        let restart = role.is_liveness()
+            && process::status::record_liveness_check(&service_name, result.success)
+                .is_ok_and(|counts| counts.failures as i32 > config.max_failures);
+        if restart {
             warn!(
-                "[{}] health check has error: {}",
-                service_name,
-                r.err().unwrap()
+                "{} failure count for [{}] has exceeded the threshold, preparing to restart it",
+                role, &service_name
             );
-            thread::sleep(Duration::from_secs(check_interval as u64));
-            continue;
-        }
-        let success = r.unwrap();
-        if !success {
-            event::send_process_event(&service_name, EventType::Unhealthy, None, None);
-            let fail_times = incr_fail_times(&service_name);
-            let restart: bool = fail_times > config.max_failures;
-            if restart {
-                warn!("health check failure count for [{}] has exceeded the threshold, preparing to restart it", &service_name);
-                process::manager::restart_service(&service_name).unwrap_or_else(|err| {
-                    warn!("restart [{}] failed: {}", &service_name, err);
-                });
-                check_interval += config.start_period.unwrap_or(0);
-            }
-        } else {
-            event::send_process_event(&service_name, EventType::Healthy, None, None);
+            process::manager::restart_service(&service_name).unwrap_or_else(|err| {
+                warn!("restart [{}] failed: {}", &service_name, err);
+            });
+            check_interval += config.start_period.unwrap_or(0);
         }
         thread::sleep(Duration::from_secs(check_interval as u64));
     }
 }
 
-fn check(service_name: &str, config: &HealthCheckConfig) -> Result<bool> {
+//log类型的健康检查由服务输出的写入线程匹配日志，这里只负责超时处理
+fn watch_log_health(service_name: String, config: HealthCheckConfig, watcher_id: u64) {
+    let start_time = Instant::now();
+    loop {
+        if !is_current_watcher(&service_name, watcher_id) {
+            return;
+        }
+        let healthy = process::status::find_readonly_proc_runtime(&service_name)
+            .map(|p| p.health == Some(true))
+            .unwrap_or(false);
+        if healthy {
+            return;
+        }
+        let timeout = config
+            .log_timeout
+            .map(|t| Duration::from_secs(t.max(0) as u64));
+        if timeout.is_some_and(|t| start_time.elapsed() > t) {
+            let reason = format!(
+                "no log line matched \"{}\" within {}s",
+                config.test_target,
+                config.log_timeout.unwrap_or_default()
+            );
+            warn!("[{}] {}, stopping it", service_name, reason);
+            process::manager::stop_service(&service_name).unwrap_or_else(|err| {
+                warn!("stop [{}] failed: {}", service_name, err);
+            });
+            process::manager::wait_for_exit_recorded(&service_name, Duration::from_secs(5));
+            process::status::update_proc_to_failed(&service_name, &reason).unwrap_or_else(|err| {
+                warn!("update [{}] status failed: {}", service_name, err);
+            });
+            return;
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+//服务本次运行的日志匹配检查所使用的正则表达式
+pub(crate) fn log_matcher(config: &ServiceConfig) -> Option<Regex> {
+    let readiness = config.readiness_check()?;
+    if readiness.test_type != HealthCheckType::Log {
+        return None;
+    }
+    Regex::new(&readiness.test_target).ok()
+}
+
+//服务输出中出现匹配的日志时调用
+pub(crate) fn on_log_matched(service_name: &str, line: &str) {
+    info!("[{}] is ready, matched log: {}", service_name, line);
+    let _ = process::status::record_readiness_check(service_name, true);
+    event::send_process_event(
+        service_name,
+        EventType::Healthy,
+        Some(line.to_string()),
+        None,
+    );
+}
+
+//单次检查的结果，message作为最近一次检查结果的原因记录在服务的运行状态中
+#[derive(Debug)]
+struct CheckResult {
+    success: bool,
+    message: Option<String>,
+}
+
+impl From<bool> for CheckResult {
+    fn from(success: bool) -> Self {
+        CheckResult {
+            success,
+            message: None,
+        }
+    }
+}
+
+fn check(
+    service_name: &str,
+    config: &HealthCheckConfig,
+    http_probe: Option<&HttpProbe>,
+) -> Result<CheckResult> {
+    let timeout = config
+        .timeout
+        .map_or(DEFAULT_PROBE_TIMEOUT, |t| Duration::from_secs(t as u64));
     match config.test_type {
-        HealthCheckType::Http => return test_with_http(&config.test_target.clone()),
-        HealthCheckType::Tcp => return test_with_tcp(&config.test_target.clone()),
-        HealthCheckType::Cmd => return test_with_cmd(&config.test_target.clone()),
-        _ => return test_with_process(service_name),
+        HealthCheckType::Http => {
+            let url = &config.test_target;
+            return match http_probe {
+                Some(probe) => probe.test(url, &config.http, timeout),
+                None => HttpProbe::new(&config.http)?.test(url, &config.http, timeout),
+            };
+        }
+        HealthCheckType::Tcp => {
+            return test_with_tcp(&config.test_target.clone(), timeout).map(CheckResult::from)
+        }
+        HealthCheckType::Cmd => {
+            //cmd方式的健康检查与服务进程使用相同的工作目录及环境变量
+            let proc_runtime = process::status::find_readonly_proc_runtime(service_name)?;
+            return test_with_cmd(&proc_runtime.config, &cmd_line(config), timeout);
+        }
+        _ => return test_with_process(service_name).map(CheckResult::from),
     }
 }
 
-fn incr_fail_times(service_name: &str) -> i32 {
-    let mut service_statuses = SERVICES_HEALTH_STATUS.write().unwrap();
-    let fail_times = service_statuses.entry(service_name.to_owned()).or_insert(0);
-    *fail_times += 1;
-    *fail_times
+//就绪检查失败时服务立即变为不健康，连续成功success_threshold次后才重新变为健康
+fn on_readiness_result(
+    service_name: &str,
+    config: &HealthCheckConfig,
+    success: bool,
+    message: Option<String>,
+) {
+    let counts = match process::status::record_readiness_check(service_name, success) {
+        Ok(counts) => counts,
+        Err(err) => {
+            warn!("record [{}] readiness check failed: {}", service_name, err);
+            return;
+        }
+    };
+    if !success {
+        event::send_process_event(service_name, EventType::Unhealthy, message, None);
+    } else if counts.successes >= config.success_threshold {
+        event::send_process_event(service_name, EventType::Healthy, message, None);
+    }
 }
 
 fn test_with_process(service_name: &str) -> Result<bool> {
     Ok(process::status::is_running_by_name(service_name))
 }
 
-fn test_with_http(url: &str) -> Result<bool> {
-    let req = reqwest::blocking::get(url);
-    if req.is_err() {
-        return Err(req.unwrap_err().into());
+//http方式的检查所需的client及编译好的请求参数
+struct HttpProbe {
+    client: Client,
+    method: Method,
+    headers: HeaderMap,
+    body_regex: Option<Regex>,
+}
+
+impl HttpProbe {
+    fn new(config: &HttpCheckConfig) -> Result<Self> {
+        let redirect = match config.max_redirects {
+            0 => redirect::Policy::none(),
+            max => redirect::Policy::limited(max),
+        };
+        let client = Client::builder()
+            .redirect(redirect)
+            .danger_accept_invalid_certs(config.insecure)
+            .build()?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in config.headers.iter() {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        let body_regex = config.body_regex.as_deref().map(Regex::new).transpose()?;
+        Ok(HttpProbe {
+            client,
+            method: Method::from_bytes(config.method.as_bytes())?,
+            headers,
+            body_regex,
+        })
+    }
+
+    fn test(&self, url: &str, config: &HttpCheckConfig, timeout: Duration) -> Result<CheckResult> {
+        let failed = |message: String| CheckResult {
+            success: false,
+            message: Some(message),
+        };
+        let timed_out = |err: reqwest::Error| -> anyhow::Error {
+            if err.is_timeout() {
+                TimedOut(timeout).into()
+            } else {
+                err.into()
+            }
+        };
+        let resp = self
+            .client
+            .request(self.method.clone(), url)
+            .headers(self.headers.clone())
+            .timeout(timeout)
+            .send()
+            .map_err(timed_out)?;
+        let status = resp.status().as_u16();
+        let status_ok = if config.expected_status.is_empty() {
+            resp.status().is_success()
+        } else {
+            config.expected_status.iter().any(|r| r.contains(status))
+        };
+        if !status_ok {
+            return Ok(failed(format!("unexpected status {}", status)));
+        }
+        if self.body_regex.is_none() && config.json_match.is_none() {
+            return Ok(true.into());
+        }
+        let body = resp.text().map_err(timed_out)?;
+        if let Some(re) = self.body_regex.as_ref() {
+            if !re.is_match(&body) {
+                return Ok(failed(format!("response body does not match {}", re)));
+            }
+        }
+        if let Some(json_match) = config.json_match.as_ref() {
+            let actual = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|json| json.pointer(&json_match.pointer).cloned());
+            if actual.as_ref() != Some(&json_match.value) {
+                return Ok(failed(format!(
+                    "expected {} at {}, got {}",
+                    json_match.value,
+                    json_match.pointer,
+                    actual.map_or_else(|| "nothing".to_string(), |v| v.to_string())
+                )));
+            }
+        }
+        Ok(true.into())
+    }
+}
+
+fn test_with_tcp(address: &str, timeout: Duration) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+    let socket_addr = resolve_address(address, timeout)?;
+    //域名解析与建立连接共用同一个超时时间
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(TimedOut(timeout).into());
+    }
+    match TcpStream::connect_timeout(&socket_addr, remaining) {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::TimedOut => Err(TimedOut(timeout).into()),
+        Err(_) => Ok(false),
     }
-    let status = req.unwrap().status();
-    Ok(status.is_success())
 }
 
-fn test_with_tcp(address: &str) -> Result<bool> {
-    let socket_addrs = address.to_socket_addrs()?.next().ok_or(io::Error::new(
-        io::ErrorKind::Other,
-        format!("{} {}", "can not convert to address:", address),
-    ))?;
-    TcpStream::connect_timeout(&socket_addrs, Duration::from_secs(5))
-        .map(|_| true)
-        .or_else(|_| Ok(false))
+//域名解析可能长时间阻塞，在单独的线程中解析，超时后不再等待其结果
+fn resolve_address(address: &str, timeout: Duration) -> Result<SocketAddr> {
+    let (sender, receiver) = mpsc::channel();
+    let target = address.to_string();
+    thread::spawn(move || {
+        let _ = sender.send(target.to_socket_addrs().map(|mut addrs| addrs.next()));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(Some(socket_addr))) => Ok(socket_addr),
+        Ok(Ok(None)) => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} {}", "can not convert to address:", address),
+        )
+        .into()),
+        Ok(Err(err)) => Err(err.into()),
+        Err(_) => Err(TimedOut(timeout).into()),
+    }
 }
 
-fn test_with_cmd(cmd: &str) -> Result<bool> {
-    // 分割命令字符串以获取命令名和参数
-    let parts: Vec<&str> = cmd.split_whitespace().collect();
-    if parts.is_empty() {
+//cmd方式检查的命令：配置了test_cmd时直接执行，否则通过shell执行test_target
+fn cmd_line(config: &HealthCheckConfig) -> Vec<String> {
+    if !config.test_cmd.is_empty() {
+        return config.test_cmd.clone();
+    }
+    if config.test_target.trim().is_empty() {
+        return Vec::new();
+    }
+    let shell: [&str; 2] = if cfg!(windows) {
+        ["cmd", "/C"]
+    } else {
+        ["sh", "-c"]
+    };
+    let mut cmd_line: Vec<String> = shell.iter().map(|s| s.to_string()).collect();
+    cmd_line.push(config.test_target.clone());
+    cmd_line
+}
+
+fn test_with_cmd(
+    conf: &ServiceConfig,
+    cmd_line: &[String],
+    timeout: Duration,
+) -> Result<CheckResult> {
+    if cmd_line.is_empty() {
         return Err(anyhow!("Command cannot be empty"));
     }
-    // 分别获取命令名和参数
-    let command = parts[0];
-    let args = &parts[1..];
-    // 创建并执行命令
-    let output = Command::new(command).args(args).output()?;
-    // 根据命令的退出状态判断健康状态
-    // 这里假设如果命令成功执行（退出状态码为0），则进程健康
-    Ok(output.status.success())
+    let mut cmd = exec::build_command(conf, cmd_line)?;
+    let (status, output) = exec::run_with_output(&mut cmd, timeout, MAX_CHECK_OUTPUT)?;
+    // 根据命令的退出状态判断健康状态，命令的输出作为检查结果的原因
+    let message = if output.is_empty() {
+        status.to_string()
+    } else {
+        output
+    };
+    Ok(CheckResult {
+        success: status.success(),
+        message: Some(message),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{JsonMatchConfig, StatusRange};
+
+    fn test_with_http(url: &str, timeout: Duration) -> Result<bool> {
+        let config = HttpCheckConfig::default();
+        Ok(HttpProbe::new(&config)?
+            .test(url, &config, timeout)?
+            .success)
+    }
+
+    //在指定环境变量的服务中执行cmd方式的检查
+    fn run_cmd(yaml: &str, environment: HashMap<String, String>) -> Result<CheckResult> {
+        let conf = ServiceConfig {
+            name: "health-check-test".to_string(),
+            environment,
+            ..Default::default()
+        };
+        let healthcheck: HealthCheckConfig = serde_yaml::from_str(yaml)?;
+        let timeout = healthcheck
+            .timeout
+            .map_or(DEFAULT_PROBE_TIMEOUT, |t| Duration::from_secs(t as u64));
+        test_with_cmd(&conf, &cmd_line(&healthcheck), timeout)
+    }
 
     #[test]
     fn test_http_success() {
         let url = "https://cn.bing.com"; // 替换为一个始终可用的URL
-        assert_eq!(test_with_http(url).unwrap(), true);
+        assert_eq!(test_with_http(url, DEFAULT_PROBE_TIMEOUT).unwrap(), true);
     }
 
     #[test]
     fn test_http_failure() {
         let url = "http://thisurldoesnotexist.tld"; // 一个不存在的URL
-        assert!(test_with_http(url).is_err());
+        assert!(test_with_http(url, DEFAULT_PROBE_TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn test_http_conditions() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/health", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                //只有携带了token的POST请求才返回200
+                let authorized = request.method() == &tiny_http::Method::Post
+                    && request
+                        .headers()
+                        .iter()
+                        .any(|h| h.field.equiv("X-Token") && h.value == "secret");
+                let code = if authorized { 200 } else { 503 };
+                let body = r#"{"status":"UP","checks":{"db":"DOWN"}}"#;
+                let _ =
+                    request.respond(tiny_http::Response::from_string(body).with_status_code(code));
+            }
+        });
+        let mut config = HttpCheckConfig::default();
+        let probe = |config: &HttpCheckConfig| {
+            HttpProbe::new(config)
+                .unwrap()
+                .test(&url, config, DEFAULT_PROBE_TIMEOUT)
+                .unwrap()
+        };
+        let result = probe(&config);
+        assert!(!result.success);
+        assert_eq!(result.message.unwrap(), "unexpected status 503");
+        let probe = |config: &HttpCheckConfig| probe(config).success;
+        config.expected_status = vec![StatusRange {
+            start: 500,
+            end: 599,
+        }];
+        assert!(probe(&config));
+        config.expected_status.clear();
+        config.method = "POST".to_string();
+        config
+            .headers
+            .insert("X-Token".to_string(), "secret".to_string());
+        assert!(probe(&config));
+        config.body_regex = Some(r#""status":\s*"UP""#.to_string());
+        assert!(probe(&config));
+        config.json_match = Some(JsonMatchConfig {
+            pointer: "/checks/db".to_string(),
+            value: serde_json::json!("UP"),
+        });
+        assert!(!probe(&config));
     }
 
     #[test]
     fn test_tcp_success() {
         let address = "baidu.com:80"; // 替换为一个始终可用的地址
-        assert_eq!(test_with_tcp(address).unwrap(), true);
+        assert_eq!(test_with_tcp(address, DEFAULT_PROBE_TIMEOUT).unwrap(), true);
     }
 
     #[test]
     fn test_tcp_failure() {
         let address = "256.256.256.256:80"; // 一个无效的地址
-        assert_eq!(test_with_tcp(address).is_err(), true);
+        assert_eq!(test_with_tcp(address, DEFAULT_PROBE_TIMEOUT).is_err(), true);
+    }
+
+    #[test]
+    fn test_tcp_unresolvable_host() {
+        //无法解析的域名在超时时间内返回错误，不会一直阻塞
+        let timeout = Duration::from_millis(500);
+        let start = Instant::now();
+        let result = test_with_tcp("unresolvable-host.invalid:80", timeout);
+        assert!(start.elapsed() < timeout + Duration::from_millis(200));
+        assert!(result.is_err());
+        //解析线程尚未返回时，超时作为TimedOut错误返回
+        let err = test_with_tcp("unresolvable-host.invalid:80", Duration::ZERO).unwrap_err();
+        assert!(err.downcast_ref::<TimedOut>().is_some());
     }
 
     #[test]
     fn test_cmd_success() {
-        let cmd = "echo Hello World"; // 替换为一个始终成功的命令
-        assert_eq!(test_with_cmd(cmd).unwrap(), true);
+        let result = run_cmd(
+            "{test_type: cmd, test_target: echo Hello World}",
+            HashMap::new(),
+        );
+        let result = result.unwrap();
+        assert!(result.success);
+        assert_eq!(result.message.unwrap(), "Hello World");
     }
 
     #[test]
     fn test_cmd_failure() {
-        let cmd = "false"; // 大多数系统上一个始终失败的命令
-        assert_eq!(test_with_cmd(cmd).is_err(), true);
+        //命令执行失败时检查结果为不健康，输出记录为原因
+        let result = run_cmd(
+            "{test_type: cmd, test_target: 'echo broken >&2; false'}",
+            HashMap::new(),
+        )
+        .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.message.unwrap(), "broken");
+    }
+
+    #[test]
+    fn test_cmd_with_environment() {
+        let envs = HashMap::from([("HEALTH_CHECK_FILE".to_string(), "/".to_string())]);
+        let yaml = "{test_type: cmd, test_target: printenv HEALTH_CHECK_FILE}";
+        assert!(run_cmd(yaml, envs).unwrap().success);
+        assert!(!run_cmd(yaml, HashMap::new()).unwrap().success);
+    }
+
+    #[test]
+    fn test_cmd_shell_and_argv() {
+        let yaml = r#"{test_type: cmd, test_target: 'echo "a b" | tr a c && echo ok'}"#;
+        assert_eq!(
+            run_cmd(yaml, HashMap::new()).unwrap().message.unwrap(),
+            "c b\nok"
+        );
+        //argv列表不经过shell，参数中的特殊字符原样传递
+        let yaml = r#"{test_type: cmd, test_cmd: ["echo", "$HOME && |"]}"#;
+        assert_eq!(
+            run_cmd(yaml, HashMap::new()).unwrap().message.unwrap(),
+            "$HOME && |"
+        );
+    }
+
+    #[test]
+    fn test_cmd_timeout() {
+        let yaml = "{test_type: cmd, test_target: sleep 5, timeout: 1}";
+        let err = run_cmd(yaml, HashMap::new()).unwrap_err();
+        assert!(err.is::<TimedOut>());
     }
 
     #[test]
     fn test_cmd_empty() {
-        let cmd = ""; // 一个空命令
-        assert!(test_with_cmd(cmd).is_err());
+        assert!(run_cmd("{test_type: cmd, test_target: ''}", HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_log_check_timeout_marks_failed() {
+        let name = "log-timeout-test";
+        let readiness: HealthCheckConfig =
+            serde_yaml::from_str("{test_type: log, test_target: started, log_timeout: 1}").unwrap();
+        process::status::add_test_process(ServiceConfig {
+            name: name.to_string(),
+            readiness: Some(readiness.clone()),
+            ..Default::default()
+        });
+        let watcher_id = set_watch_flag(name);
+        watch_log_health(name.to_string(), readiness, watcher_id);
+        let proc_runtime = process::status::find_readonly_proc_runtime(name).unwrap();
+        assert_eq!(proc_runtime.state, process::status::ProcessState::Failed);
+        assert_eq!(
+            proc_runtime.last_failure.unwrap(),
+            "no log line matched \"started\" within 1s"
+        );
     }
 }
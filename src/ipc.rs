@@ -0,0 +1,374 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    process::{
+        self,
+        status::{ProcessState, ProcessStatus},
+    },
+    reload,
+};
+
+const SOCKET_DIR_NAME: &str = ".ctl";
+const SOCKET_FILE_NAME: &str = "process-compose.sock";
+
+//通过本地控制通道发送给运行中的process-compose的命令
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CtlRequest {
+    pub(crate) command: String,
+    pub(crate) service: Option<String>,
+    //命令的其它参数，如scale的实例数
+    #[serde(default)]
+    pub(crate) args: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CtlResponse {
+    pub(crate) ok: bool,
+    pub(crate) message: String,
+    #[serde(default)]
+    pub(crate) services: Vec<ProcessStatus>,
+}
+
+impl CtlResponse {
+    fn ok(message: &str, services: Vec<ProcessStatus>) -> Self {
+        CtlResponse {
+            ok: true,
+            message: message.to_string(),
+            services,
+        }
+    }
+
+    fn err(message: &str) -> Self {
+        CtlResponse {
+            ok: false,
+            message: message.to_string(),
+            services: Vec::new(),
+        }
+    }
+}
+
+//控制通道使用的unix socket文件，位于app_data_home下仅当前用户可访问的目录中
+pub fn socket_path() -> PathBuf {
+    PathBuf::from(config::current_config().app_data_home)
+        .join(SOCKET_DIR_NAME)
+        .join(SOCKET_FILE_NAME)
+}
+
+fn execute(request: CtlRequest) -> CtlResponse {
+    let command = request.command.as_str();
+    if command == "ps" {
+        return CtlResponse::ok("", process::status::get_all_proc_status());
+    }
+    if command == "reload" {
+        return match reload::reload() {
+            Ok(summary) => CtlResponse::ok(&summary, Vec::new()),
+            Err(err) => CtlResponse::err(&err.to_string()),
+        };
+    }
+    let name = match request.service {
+        Some(name) => name,
+        None => return CtlResponse::err(&format!("{} requires a service name", command)),
+    };
+    let result = match command {
+        "scale" => match request.args.first().map(|n| n.parse::<u32>()) {
+            Some(Ok(replicas)) => process::manager::scale_service(&name, replicas),
+            _ => return CtlResponse::err("usage: scale <service> <replicas>"),
+        },
+        "health" => {
+            //服务名对应其所有实例
+            let statuses: Vec<_> = process::status::get_all_proc_status()
+                .into_iter()
+                .filter(|s| s.name == name || s.service == name)
+                .collect();
+            return if statuses.is_empty() {
+                CtlResponse::err(&format!("service {} not found", name))
+            } else {
+                CtlResponse::ok("", statuses)
+            };
+        }
+        _ => process::manager::run_operator_action(command, vec![name.clone()]),
+    };
+    match result {
+        Ok(_) => {
+            let message = [command, &name]
+                .into_iter()
+                .chain(request.args.iter().map(|a| a.as_str()));
+            CtlResponse::ok(&message.collect::<Vec<_>>().join(" "), Vec::new())
+        }
+        Err(err) => CtlResponse::err(&err.to_string()),
+    }
+}
+
+//客户端：把命令发送给运行中的实例并输出结果，返回命令是否执行成功
+pub fn run_client(command: &str, service: Option<String>, args: Vec<String>) -> Result<bool> {
+    let request = CtlRequest {
+        command: command.to_string(),
+        service,
+        args,
+    };
+    let response = send_request(&request)?;
+    if !response.ok {
+        eprintln!("{}", response.message);
+        return Ok(false);
+    }
+    match command {
+        "ps" => print_status_table(&response.services),
+        "health" => {
+            for status in response.services.iter() {
+                let health = match status.health {
+                    Some(true) => "healthy",
+                    Some(false) => "unhealthy",
+                    None => "unknown",
+                };
+                let state = format!("{:?}", status.state).to_lowercase();
+                println!("{} {} {}", status.name, state, health);
+                //最近一次检查结果的原因，如cmd方式检查命令的输出
+                if let Some(message) = status.health_message.as_ref() {
+                    for line in message.lines() {
+                        println!("  {}", line);
+                    }
+                }
+            }
+            //服务不健康时以非0退出码退出，方便脚本判断
+            return Ok(response.services.iter().all(|s| s.health == Some(true)));
+        }
+        _ => println!("{}", response.message),
+    }
+    Ok(true)
+}
+
+fn print_status_table(services: &[ProcessStatus]) {
+    let width = services
+        .iter()
+        .map(|s| s.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+    let print_row = |cols: [&str; 6]| {
+        println!(
+            "{:<width$}  {:<9}  {:<8}  {:<9}  {:<8}  {}",
+            cols[0],
+            cols[1],
+            cols[2],
+            cols[3],
+            cols[4],
+            cols[5],
+            width = width
+        );
+    };
+    print_row(["NAME", "STATE", "PID", "HEALTH", "RESTARTS", "LAST ERROR"]);
+    for s in services {
+        let state = format!("{:?}", s.state).to_lowercase();
+        let pid = s.pid.map_or("-".to_string(), |p| p.to_string());
+        let health = match s.health {
+            Some(true) => "healthy",
+            Some(false) => "unhealthy",
+            None => "-",
+        };
+        let last_err = match s.state {
+            ProcessState::Completed => String::new(),
+            _ => s
+                .last_failure
+                .clone()
+                .or(s.exit_err.clone())
+                .unwrap_or_default(),
+        };
+        print_row([
+            &s.name,
+            &state,
+            &pid,
+            health,
+            &s.restart_count.to_string(),
+            &last_err,
+        ]);
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use self::unix::{cleanup, start_server};
+
+#[cfg(target_os = "linux")]
+fn send_request(request: &CtlRequest) -> Result<CtlResponse> {
+    unix::send_request(request)
+}
+
+#[cfg(target_os = "linux")]
+mod unix {
+    use std::{
+        fs::{self, Permissions},
+        io::{BufRead, BufReader, Write},
+        os::unix::{
+            fs::{DirBuilderExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+        thread,
+    };
+
+    use anyhow::{Error, Result};
+    use log::{error, info, warn};
+
+    use super::{execute, socket_path, CtlRequest, CtlResponse};
+
+    //启动本地控制通道，socket文件仅允许当前用户访问
+    pub fn start_server() -> Result<()> {
+        let path = socket_path();
+        let listener = bind(&path)?;
+        info!("control channel is listening on {}", path.display());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        thread::spawn(move || {
+                            if let Err(err) = handle_client(stream) {
+                                warn!("handle control command failed: {}", err);
+                            }
+                        });
+                    }
+                    Err(err) => warn!("accept control connection failed: {}", err),
+                }
+            }
+            error!("control channel has been stopped!");
+        });
+        Ok(())
+    }
+
+    //bind之前先限制socket所在目录的权限，避免socket文件创建后、修改权限前被其他用户连接
+    pub(super) fn bind(path: &Path) -> Result<UnixListener> {
+        if let Some(dir) = path.parent() {
+            if !dir.exists() {
+                fs::create_dir_all(dir.parent().unwrap_or(dir))?;
+                fs::DirBuilder::new().mode(0o700).create(dir)?;
+            }
+            fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+        }
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(Error::msg(format!(
+                    "another process-compose instance is listening on {}",
+                    path.display()
+                )));
+            }
+            //上次异常退出时残留的socket文件
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    pub fn cleanup() {
+        let _ = fs::remove_file(socket_path());
+    }
+
+    fn handle_client(stream: UnixStream) -> Result<()> {
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let response = match serde_json::from_str::<CtlRequest>(&line) {
+            Ok(request) => {
+                info!(
+                    "received control command: {} {}",
+                    request.command,
+                    request.service.clone().unwrap_or_default()
+                );
+                execute(request)
+            }
+            Err(err) => CtlResponse::err(&format!("invalid request: {}", err)),
+        };
+        let mut writer = &stream;
+        writer.write_all(serde_json::to_string(&response)?.as_bytes())?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn send_request(request: &CtlRequest) -> Result<CtlResponse> {
+        let path = socket_path();
+        let mut stream = UnixStream::connect(&path).map_err(|e| {
+            Error::msg(format!(
+                "can not connect to process-compose via {}, is it running? {}",
+                path.display(),
+                e
+            ))
+        })?;
+        stream.write_all(serde_json::to_string(request)?.as_bytes())?;
+        stream.write_all(b"\n")?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start_server() -> Result<()> {
+    Err(anyhow::Error::msg(
+        "control channel is not supported on this platform, use the http api instead",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cleanup() {}
+
+#[cfg(not(target_os = "linux"))]
+fn send_request(_request: &CtlRequest) -> Result<CtlResponse> {
+    Err(anyhow::Error::msg(
+        "control channel is not supported on this platform, use the http api instead",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_requires_service_name() {
+        let response = execute(CtlRequest {
+            command: "restart".to_string(),
+            service: None,
+            args: Vec::new(),
+        });
+        assert!(!response.ok);
+        assert_eq!(response.message, "restart requires a service name");
+    }
+
+    #[test]
+    fn test_scale_requires_replicas() {
+        let response = execute(CtlRequest {
+            command: "scale".to_string(),
+            service: Some("web".to_string()),
+            args: vec!["many".to_string()],
+        });
+        assert!(!response.ok);
+        assert_eq!(response.message, "usage: scale <service> <replicas>");
+    }
+
+    #[test]
+    fn test_restart_unknown_service() {
+        let response = execute(CtlRequest {
+            command: "restart".to_string(),
+            service: Some("no-such-service".to_string()),
+            args: Vec::new(),
+        });
+        assert!(!response.ok);
+        assert_eq!(response.message, "service no-such-service not found");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_socket_only_accessible_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir()
+            .join(format!("pc-ipc-test-{}", std::process::id()))
+            .join(SOCKET_DIR_NAME)
+            .join(SOCKET_FILE_NAME);
+        let _listener = unix::bind(&path).unwrap();
+        let mode = |p: &std::path::Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+        assert_eq!(mode(&path), 0o600);
+        std::fs::remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+}
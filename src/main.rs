@@ -15,16 +15,20 @@ use std::{
 use sys_service::{control::control, manager::SysServiceProgram};
 
 use crate::{
-    config::{analyze_service_dependencies, load_config},
+    config::{analyze_service_dependencies, instance_configs, load_config, ServiceConfig},
     event::ProcessEvent,
 };
 
+mod api;
 mod config;
+mod console;
 mod env;
 mod event;
 mod health;
+mod ipc;
 mod logger;
 mod process;
+mod reload;
 mod sys_service;
 
 fn main() {
@@ -35,8 +39,29 @@ fn main() {
         .unwrap();
     logger::change_log_level(config::current_config().log_level.as_str());
     let args = Args::parse();
+    //up模式：前台运行，并将各服务的输出汇总到终端
+    if args.service_action.as_deref() == Some("up") {
+        console::enable(args.include, args.exclude);
+        if let Err(e) = run() {
+            error!("process-manager run failed: {}", e);
+        }
+        wait_for_signal();
+        return;
+    }
     if args.service_action.is_some() {
         let action = args.service_action.unwrap();
+        //带服务名或查询类的命令发送给运行中的process-compose，其它的作用于系统服务本身
+        if args.service_name.is_some() || action == "ps" || action == "reload" {
+            match ipc::run_client(&action, args.service_name, args.action_args) {
+                Ok(true) => {}
+                Ok(false) => exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+            return;
+        }
         if let Err(err) = control(&action) {
             error!("service action {:?} failed: {}", action, err);
         } else {
@@ -67,8 +92,8 @@ impl SysServiceProgram for Program {
     }
 
     fn stop(&self) -> anyhow::Result<()> {
-        let all_services = process::status::get_all_process_name();
-        process::manager::stop_services(all_services)?;
+        ipc::cleanup();
+        process::manager::stop_all_services();
         Ok(())
     }
 }
@@ -79,16 +104,33 @@ fn run() -> Result<()> {
     let services_congfig = config.services.values().cloned().collect();
     let services_ordered = analyze_service_dependencies(&services_congfig)?;
     process::status::init_processes(&config, services_ordered)?;
-    env::create_services_home(&services_congfig)
+    let instances: Vec<ServiceConfig> = services_congfig
+        .iter()
+        .map(|s| instance_configs(s, s.replicas))
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+    env::create_services_home(&instances)
         .unwrap_or_else(|e| error!("create service home failed: {}", e));
     //注册服务事件处理器，并启动配置的服务
     let (tx, rx) = mpsc::channel::<ProcessEvent>();
     thread::spawn(move || {
         event::handle_process_event(tx, rx);
     });
-    let all_services = process::status::get_all_process_name();
+    //定时服务按计划运行，不在启动时拉起
+    let all_services = process::status::get_all_process_name()
+        .into_iter()
+        .filter(|name| !process::schedule::is_scheduled(name))
+        .collect();
     process::manager::start_services(all_services)
         .unwrap_or_else(|e| error!("start service failed: {}", e));
+    process::schedule::sync_schedulers();
+    if let Some(api_config) = config.api.clone() {
+        if api_config.enable {
+            api::start_server(api_config).unwrap_or_else(|e| error!("{}", e));
+        }
+    }
+    ipc::start_server().unwrap_or_else(|e| error!("start control channel failed: {}", e));
+    reload::watch_reload_signal().unwrap_or_else(|e| error!("watch SIGHUP failed: {}", e));
     Ok(())
 }
 
@@ -101,8 +143,7 @@ fn wait_for_signal() {
         thread::sleep(Duration::from_secs(1));
     }
     info!("received a terminate signal,try to stop all services...");
-    let all_services = process::status::get_all_process_name();
-    process::manager::stop_services(all_services)
-        .unwrap_or_else(|e| error!("stop service failed: {}", e));
+    ipc::cleanup();
+    process::manager::stop_all_services();
     exit(0);
 }
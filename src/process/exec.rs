@@ -0,0 +1,195 @@
+use std::{
+    fmt,
+    io::Read,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, Result};
+use wait_timeout::ChildExt;
+
+#[cfg(target_os = "linux")]
+use super::platform::linux::{before_exec, kill_process};
+#[cfg(target_os = "windows")]
+use super::platform::windows::{before_exec, kill_process};
+use crate::{config::ServiceConfig, env};
+
+//基于服务的工作目录和环境变量构建命令，start_cmd、stop_cmd等均通过该方法执行
+pub(crate) fn build_command(conf: &ServiceConfig, cmd_line: &[String]) -> Result<Command> {
+    let (command, params) = cmd_line
+        .split_first()
+        .ok_or_else(|| Error::msg("command is empty"))?;
+    let current_dir = env::get_service_work_dir(conf);
+    let envs = env::resolve_service_environment(conf)?;
+    //优先使用服务自身配置的PATH查找命令
+    let real_cmd = env::resolve_command(command, &current_dir, envs.get("PATH"))?;
+    let mut cmd = Command::new(&real_cmd);
+    cmd.args(params);
+    cmd.envs(envs);
+    cmd.current_dir(&current_dir);
+    Ok(cmd)
+}
+
+//命令或健康检查超时，调用方可据此与其它错误区分
+#[derive(Debug)]
+pub(crate) struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+//执行命令并等待其结束，超时后杀掉命令及其启动的所有进程并返回TimedOut错误
+pub(crate) fn run_with_timeout(cmd: &mut Command, timeout: Duration) -> Result<ExitStatus> {
+    let mut child = spawn(cmd)?;
+    wait_or_kill(&mut child, timeout)
+}
+
+//执行命令并收集其标准输出及错误输出，每路只保留前limit字节，超时处理与run_with_timeout相同
+pub(crate) fn run_with_output(
+    cmd: &mut Command,
+    timeout: Duration,
+    limit: usize,
+) -> Result<(ExitStatus, String)> {
+    let deadline = Instant::now() + timeout;
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = spawn(cmd)?;
+    let (sender, receiver) = mpsc::channel();
+    spawn_reader(child.stdout.take(), 0, limit, sender.clone());
+    spawn_reader(child.stderr.take(), 1, limit, sender);
+    let status = wait_or_kill(&mut child, timeout)?;
+    //命令启动的后台进程可能继续占用输出管道，最多等待到超时时间
+    let mut outputs = [String::new(), String::new()];
+    while let Ok((index, text)) =
+        receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        outputs[index] = text;
+    }
+    let output = outputs
+        .into_iter()
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((status, output))
+}
+
+//命令在单独的进程组中执行，超时后可以杀掉整个进程组，如sh -c启动的管道命令
+fn spawn(cmd: &mut Command) -> Result<Child> {
+    before_exec(cmd)?;
+    cmd.spawn().map_err(|e| {
+        Error::msg(format!(
+            "spawn {} failed: {}",
+            cmd.get_program().to_string_lossy(),
+            e
+        ))
+    })
+}
+
+fn wait_or_kill(child: &mut Child, timeout: Duration) -> Result<ExitStatus> {
+    match child.wait_timeout(timeout)? {
+        Some(status) => Ok(status),
+        None => {
+            if kill_process(child.id()).is_err() {
+                let _ = child.kill();
+            }
+            let _ = child.wait();
+            Err(TimedOut(timeout).into())
+        }
+    }
+}
+
+fn spawn_reader<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    index: usize,
+    limit: usize,
+    sender: Sender<(usize, String)>,
+) {
+    let Some(pipe) = pipe else { return };
+    thread::spawn(move || {
+        let _ = sender.send((index, read_truncated(pipe, limit)));
+    });
+}
+
+//读取全部输出以免命令阻塞在写管道上，超过limit的部分丢弃并以...结尾
+fn read_truncated(mut pipe: impl Read, limit: usize) -> String {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 4096];
+    loop {
+        match pipe.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = limit.saturating_sub(kept.len());
+                truncated |= n > room;
+                kept.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+    }
+    let mut text = String::from_utf8_lossy(&kept).trim().to_string();
+    if truncated {
+        text.push_str("...");
+    }
+    text
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_with_timeout() {
+        let status = run_with_timeout(Command::new("true").arg(""), Duration::from_secs(5));
+        assert!(status.unwrap().success());
+        let mut cmd = Command::new("sleep");
+        cmd.arg("5");
+        let err = run_with_timeout(&mut cmd, Duration::from_millis(200)).unwrap_err();
+        assert_eq!(err.to_string(), "timed out after 200ms");
+        assert!(err.is::<TimedOut>());
+    }
+
+    #[test]
+    fn test_run_with_output() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo out; echo err >&2; exit 3"]);
+        let (status, output) = run_with_output(&mut cmd, Duration::from_secs(5), 100).unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(output, "out\nerr");
+        //超出限制的输出被截断，命令不会因管道写满而阻塞
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "head -c 100000 /dev/zero | tr '\\0' a"]);
+        let (status, output) = run_with_output(&mut cmd, Duration::from_secs(5), 10).unwrap();
+        assert!(status.success());
+        assert_eq!(output, "aaaaaaaaaa...");
+    }
+
+    //按命令行查找正在运行的进程
+    fn is_process_running(cmdline: &str) -> bool {
+        let expected = cmdline.replace(' ', "\0") + "\0";
+        std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| std::fs::read(e.path().join("cmdline")).ok())
+            .any(|c| c == expected.as_bytes())
+    }
+
+    #[test]
+    fn test_timeout_kills_pipeline() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 30.25 | cat"]);
+        let err = run_with_output(&mut cmd, Duration::from_secs(1), 100).unwrap_err();
+        assert!(err.is::<TimedOut>());
+        let start = Instant::now();
+        while is_process_running("sleep 30.25") && start.elapsed() < Duration::from_secs(2) {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!is_process_running("sleep 30.25"));
+    }
+}
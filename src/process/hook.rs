@@ -0,0 +1,88 @@
+use std::{fmt, io::Write, process::Stdio, time::Duration};
+
+use anyhow::{Error, Result};
+use chrono::Local;
+use log::{error, info, warn};
+
+use super::exec;
+use crate::{
+    config::{HookConfig, HookFailurePolicy, ServiceConfig},
+    env,
+};
+
+//服务生命周期中可以执行钩子命令的阶段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HookPoint {
+    PreStart,
+    PostStart,
+    PreStop,
+    PostStop,
+}
+
+impl fmt::Display for HookPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HookPoint::PreStart => "pre_start",
+            HookPoint::PostStart => "post_start",
+            HookPoint::PreStop => "pre_stop",
+            HookPoint::PostStop => "post_stop",
+        };
+        f.write_str(name)
+    }
+}
+
+fn hook_config(conf: &ServiceConfig, point: HookPoint) -> Option<&HookConfig> {
+    match point {
+        HookPoint::PreStart => conf.pre_start.as_ref(),
+        HookPoint::PostStart => conf.post_start.as_ref(),
+        HookPoint::PreStop => conf.pre_stop.as_ref(),
+        HookPoint::PostStop => conf.post_stop.as_ref(),
+    }
+}
+
+//执行服务配置的钩子命令，只有on_failure为abort的钩子执行失败时才返回错误
+pub(crate) fn run_hook(conf: &ServiceConfig, point: HookPoint) -> Result<()> {
+    let hook = match hook_config(conf, point) {
+        Some(hook) => hook,
+        None => return Ok(()),
+    };
+    info!("[{}] running {} hook", conf.name, point);
+    match execute(conf, point, hook) {
+        Ok(_) => {
+            info!("[{}] {} hook finished", conf.name, point);
+            Ok(())
+        }
+        Err(err) => {
+            let msg = format!("{} hook failed: {}", point, err);
+            if hook.on_failure == HookFailurePolicy::Ignore {
+                warn!("[{}] {}, ignored", conf.name, msg);
+                return Ok(());
+            }
+            error!("[{}] {}", conf.name, msg);
+            Err(Error::msg(msg))
+        }
+    }
+}
+
+fn execute(conf: &ServiceConfig, point: HookPoint, hook: &HookConfig) -> Result<()> {
+    let mut cmd = exec::build_command(conf, &hook.cmd)?;
+    cmd.envs(&hook.environment);
+    //钩子命令的输出追加到服务日志目录下的hook日志中
+    let mut log_file = env::open_service_append_log_file(&conf.name, "hook")?;
+    writeln!(
+        log_file,
+        "[{}] {}: {}",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        point,
+        hook.cmd.join(" ")
+    )?;
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::from(log_file.try_clone()?));
+    cmd.stderr(Stdio::from(log_file));
+    let timeout = Duration::from_secs(hook.timeout.max(0) as u64);
+    let status = exec::run_with_timeout(&mut cmd, timeout)?;
+    if !status.success() {
+        return Err(Error::msg(format!("exited with {}", status)));
+    }
+    Ok(())
+}
@@ -1,17 +1,21 @@
 #[cfg(target_os = "linux")]
 use super::platform::linux::{before_exec, kill_process, terminate_process};
 
+use super::hook::{self, HookPoint};
+use super::output::OutputCapture;
 #[cfg(target_os = "windows")]
 use super::platform::windows::{before_exec, kill_process, terminate_process};
-use super::{pending, status};
-use crate::config::ServiceConfig;
+use super::status::{ProcessRuntimeInfo, ProcessState};
+use super::{exec, pending, restart, status};
+use crate::config::{self, RestartPolicy, ServiceConfig};
 use crate::event::EventType;
-use crate::{env, event};
+use crate::{event, health};
 use anyhow::{Error, Result};
 use log::{debug, error, info, warn};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::process::{Command, Stdio};
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -25,6 +29,12 @@ pub fn start_services(services: Vec<String>) -> Result<()> {
             warn!("starting service [{}] not found:", name);
             continue;
         }
+        if service_info.as_ref().unwrap().config.restart == RestartPolicy::UnlessStopped
+            && status::is_stopped_by_operator(name)
+        {
+            info!("[{}] was stopped by operator, skip starting it", name);
+            continue;
+        }
         let dep_ok = status::check_dep_ok(name);
         //仅启动没有依赖的服务，其它服务加入待启动列表
         if dep_ok {
@@ -32,7 +42,7 @@ pub fn start_services(services: Vec<String>) -> Result<()> {
         } else {
             info!("[{}] has dependencies, add to pending list", name);
             let deps = service_info.unwrap().config.depends_on.clone().unwrap();
-            pending::add_pending_service(name, deps)
+            pending::add_pending_service(name, deps.into_keys().collect())
         }
     }
     Ok(())
@@ -40,6 +50,13 @@ pub fn start_services(services: Vec<String>) -> Result<()> {
 
 pub fn start_service(service_name: &str) -> Result<()> {
     let proc_runtime = status::find_readonly_proc_runtime(service_name)?;
+    if proc_runtime.state == ProcessState::Failed {
+        return Err(Error::msg(format!(
+            "[{}] has failed and must be reset before starting: {}",
+            service_name,
+            proc_runtime.last_failure.unwrap_or_default()
+        )));
+    }
     let conf = proc_runtime.config;
     let pid = proc_runtime.pid;
     let svc_name = service_name.to_string();
@@ -55,6 +72,12 @@ pub fn start_service(service_name: &str) -> Result<()> {
         }
     }
     thread::spawn(move || {
+        //pre_start钩子执行失败时按照启动失败处理
+        if let Err(err) = hook::run_hook(&conf, HookPoint::PreStart) {
+            status::update_proc_to_spawn_failed(&svc_name, &err.to_string())
+                .unwrap_or_else(|e| error!("[{}] update status failed: {}", svc_name, e));
+            return;
+        }
         if let Err(err) = spawn_proc(Arc::clone(&conf)) {
             error!("[{}] exited with error: {}", svc_name, err);
         }
@@ -62,6 +85,122 @@ pub fn start_service(service_name: &str) -> Result<()> {
     Ok(())
 }
 
+//运维人员通过控制接口启动服务，同时清除服务的主动停止标记
+pub fn start_services_by_operator(services: Vec<String>) -> Result<()> {
+    for name in services.iter() {
+        status::find_readonly_proc_runtime(name)?;
+        status::mark_stopped_by_operator(name, false);
+    }
+    start_services(services)
+}
+
+//运维人员通过控制接口停止服务，unless-stopped策略的服务在process-compose重启后保持停止
+pub fn stop_services_by_operator(services: Vec<String>) -> Result<()> {
+    for name in services.iter() {
+        status::find_readonly_proc_runtime(name)?;
+        status::mark_stopped_by_operator(name, true);
+    }
+    stop_services(services)
+}
+
+pub fn restart_services_by_operator(services: Vec<String>) -> Result<()> {
+    for name in services.iter() {
+        status::find_readonly_proc_runtime(name)?;
+    }
+    for name in services.iter() {
+        status::mark_stopped_by_operator(name, false);
+        restart_service(name)?;
+    }
+    Ok(())
+}
+
+//重置失败状态的服务并重新启动
+pub fn reset_service(service_name: &str) -> Result<()> {
+    status::reset_proc_failure(service_name)?;
+    info!("[{}] has been reset", service_name);
+    start_services_by_operator(vec![service_name.to_string()])
+}
+
+//执行运维人员通过控制接口下发的操作，服务名会展开为其所有实例
+pub fn run_operator_action(action: &str, services: Vec<String>) -> Result<()> {
+    let services = resolve_instance_names(services)?;
+    match action {
+        "start" => start_services_by_operator(services),
+        "stop" => stop_services_by_operator(services),
+        "restart" => restart_services_by_operator(services),
+        "reset" => {
+            for name in services.iter() {
+                reset_service(name)?;
+            }
+            Ok(())
+        }
+        _ => Err(Error::msg(format!("unsupported action: {}", action))),
+    }
+}
+
+//服务名展开为其所有实例，实例名保持不变，未知的服务名返回错误
+fn resolve_instance_names(names: Vec<String>) -> Result<Vec<String>> {
+    let mut resolved = Vec::new();
+    for name in names {
+        if !status::service_exists(&name) {
+            return Err(Error::msg(format!("service {} not found", name)));
+        }
+        let instances = status::find_service_instances(&name);
+        if instances.is_empty() {
+            resolved.push(name);
+        } else {
+            resolved.extend(instances);
+        }
+    }
+    //同一个实例可能同时通过服务名和实例名指定，按首次出现的顺序去重
+    let mut seen = HashSet::new();
+    resolved.retain(|name| seen.insert(name.clone()));
+    Ok(resolved)
+}
+
+//调整服务运行的实例数：新增的实例会被启动，多出的实例从序号最大的开始停止并移除
+pub fn scale_service(service_name: &str, replicas: u32) -> Result<()> {
+    if replicas == 0 {
+        return Err(Error::msg("replicas must be at least 1"));
+    }
+    let service = config::find_service_config(service_name)
+        .ok_or_else(|| Error::msg(format!("service {} not found", service_name)))?;
+    if service.schedule.is_some() && replicas > 1 {
+        return Err(Error::msg(format!(
+            "[{}] is a scheduled service and can not be scaled",
+            service_name
+        )));
+    }
+    let instances = status::find_service_instances(service_name);
+    let current = instances.len() as u32;
+    if replicas > current {
+        let mut added = Vec::new();
+        for index in current..replicas {
+            added.push(status::add_process(&service, index)?);
+        }
+        let start_orders = config::analyze_service_dependencies(
+            &config::current_config()
+                .services
+                .values()
+                .cloned()
+                .collect(),
+        )?;
+        status::sort_processes(&start_orders);
+        info!("[{}] scaled up to {} replicas", service_name, replicas);
+        start_services(added)?;
+    } else {
+        for instance in instances.iter().skip(replicas as usize).rev() {
+            stop_service(instance)?;
+            wait_for_exit_recorded(instance, Duration::from_secs(5));
+            pending::remove_pending_service(instance);
+            health::stop_watch(instance.clone());
+            status::remove_process(instance);
+        }
+        info!("[{}] scaled down to {} replicas", service_name, replicas);
+    }
+    Ok(())
+}
+
 pub fn stop_services(services: Vec<String>) -> Result<()> {
     if services.len() == 0 {
         return Ok(());
@@ -72,7 +211,72 @@ pub fn stop_services(services: Vec<String>) -> Result<()> {
     Ok(())
 }
 
+//按照依赖关系的逆序停止所有服务：服务会等待依赖它的服务都退出后才停止，互不依赖的服务并行停止
+pub fn stop_all_services() {
+    let services: Vec<ProcessRuntimeInfo> = status::get_all_process_name()
+        .iter()
+        .filter_map(|name| status::find_readonly_proc_runtime(name).ok())
+        .collect();
+    let dependents = Arc::new(find_dependents(&services));
+    let stopped = Arc::new((Mutex::new(HashSet::<String>::new()), Condvar::new()));
+    let handles: Vec<_> = services
+        .iter()
+        .map(|service| {
+            let name = service.name.clone();
+            let dependents = Arc::clone(&dependents);
+            let stopped = Arc::clone(&stopped);
+            thread::spawn(move || {
+                let (lock, cvar) = &*stopped;
+                let waiting_for = dependents.get(&name).cloned().unwrap_or_default();
+                let mut done = lock.lock().unwrap();
+                while !waiting_for.iter().all(|d| done.contains(d)) {
+                    done = cvar.wait(done).unwrap();
+                }
+                drop(done);
+                if let Err(err) = do_stop_service(&name, true) {
+                    error!("stop service [{}] failed: {}", name, err);
+                }
+                wait_for_exit_recorded(&name, Duration::from_secs(5));
+                lock.lock().unwrap().insert(name);
+                cvar.notify_all();
+            })
+        })
+        .collect();
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+//计算每个服务实例被哪些实例依赖，依赖某个服务即依赖其所有实例
+fn find_dependents(services: &[ProcessRuntimeInfo]) -> HashMap<String, Vec<String>> {
+    let mut dependents: HashMap<String, Vec<String>> = HashMap::new();
+    for service in services {
+        for dep in service
+            .config
+            .depends_on
+            .iter()
+            .flat_map(|deps| deps.keys())
+        {
+            //只等待仍受管理的服务
+            for dep_instance in services.iter().filter(|s| &s.service == dep) {
+                dependents
+                    .entry(dep_instance.name.clone())
+                    .or_default()
+                    .push(service.name.clone());
+            }
+        }
+    }
+    dependents
+}
+
 pub fn stop_service(service_name: &str) -> Result<()> {
+    do_stop_service(service_name, false)
+}
+
+//shutting_down表示process-compose正在退出，此时服务必须被停止
+fn do_stop_service(service_name: &str, shutting_down: bool) -> Result<()> {
+    //主动停止的服务不再执行尚未到期的自动重启
+    restart::cancel_restart(service_name);
     let proc_runtime = status::find_readonly_proc_runtime(service_name)?;
     let pid = proc_runtime.pid;
     if pid.is_none() {
@@ -93,93 +297,182 @@ pub fn stop_service(service_name: &str) -> Result<()> {
         return Ok(());
     }
     info!("[{}] (pid: {}) is stopping", service_name, pid_val);
-    //首先尝试通过信号量的方式让进程自己退出
-    if let Err(err) = terminate_process(pid_val) {
-        warn!("signal {} (pid: {}) failed: {}", service_name, pid_val, err);
+    let conf = proc_runtime.config;
+    //pre_stop钩子执行失败且配置为abort时放弃停止服务，但process-compose退出时仍要停止，避免遗留孤儿进程
+    if let Err(err) = hook::run_hook(&conf, HookPoint::PreStop) {
+        if !shutting_down {
+            status::update_proc_runtime(service_name, |p| p.stopped_by_supervisor = false)?;
+            return Err(err);
+        }
+        warn!(
+            "[{}] {}, stop it anyway as process-compose is shutting down",
+            service_name, err
+        );
     }
-    let start_time = Instant::now();
-    let timeout_duration = Duration::from_secs(2);
-    while is_running && start_time.elapsed() <= timeout_duration {
-        thread::sleep(Duration::from_millis(200));
+    //停止命令和信号共用同一个stop_timeout
+    let stop_timeout = Duration::from_secs(conf.stop_timeout.max(0) as u64);
+    let deadline = Instant::now() + stop_timeout;
+    //配置了停止命令时先执行停止命令，让服务有机会自行完成清理
+    if !conf.stop_cmd.is_empty() {
+        run_stop_cmd(&conf, stop_timeout);
         is_running = status::is_running_by_pid(pid_val);
     }
+    //然后通过信号量的方式让进程自己退出
+    if is_running {
+        if let Err(err) = terminate_process(pid_val, conf.stop_signal) {
+            warn!("signal {} (pid: {}) failed: {}", service_name, pid_val, err);
+        }
+        while is_running && Instant::now() <= deadline {
+            thread::sleep(Duration::from_millis(200));
+            is_running = status::is_running_by_pid(pid_val);
+        }
+    }
     //如果超过规定时间进程没有退出，则强制杀掉进程
     if is_running {
-        info!("[{}] (pid: {}) is still running within the specified time after sending the interrupt signal, and is ready to be killed", service_name, pid_val);
+        warn!(
+            "[{}] (pid: {}) is still running {}s after stopping with {}, and is ready to be killed",
+            service_name, pid_val, conf.stop_timeout, conf.stop_signal
+        );
+        status::update_proc_runtime(service_name, |p| p.force_killed = true)?;
         kill_process(pid_val)?;
     }
     Ok(())
 }
 
+fn run_stop_cmd(conf: &ServiceConfig, timeout: Duration) {
+    let result = exec::build_command(conf, &conf.stop_cmd).and_then(|mut cmd| {
+        cmd.stdin(Stdio::null());
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::null());
+        exec::run_with_timeout(&mut cmd, timeout)
+    });
+    match result {
+        Ok(status) if status.success() => info!("[{}] stop_cmd finished", conf.name),
+        Ok(status) => warn!("[{}] stop_cmd exited with {}", conf.name, status),
+        Err(err) => warn!("[{}] stop_cmd failed: {}", conf.name, err),
+    }
+}
+
 pub fn restart_service(service_name: &str) -> Result<()> {
     if status::is_running_by_name(service_name) {
         stop_service(service_name)?;
+        wait_for_exit_recorded(service_name, Duration::from_secs(5));
     }
     start_service(service_name)?;
     Ok(())
 }
 
+//等待进程的退出状态被记录，避免新进程的状态被旧进程的退出覆盖
+pub(crate) fn wait_for_exit_recorded(service_name: &str, timeout: Duration) {
+    //进程退出后还需要执行post_stop钩子
+    let hook_timeout = status::find_readonly_proc_runtime(service_name)
+        .ok()
+        .and_then(|p| p.config.post_stop.as_ref().map(|h| h.timeout.max(0) as u64))
+        .unwrap_or(0);
+    let timeout = timeout + Duration::from_secs(hook_timeout);
+    let start_time = Instant::now();
+    while start_time.elapsed() <= timeout {
+        match status::find_readonly_proc_runtime(service_name) {
+            Ok(proc_runtime) if proc_runtime.pid.is_some() => {
+                thread::sleep(Duration::from_millis(100))
+            }
+            _ => return,
+        }
+    }
+    warn!("[{}] exit status was not recorded in time", service_name);
+}
+
 fn spawn_proc(conf: Arc<ServiceConfig>) -> Result<()> {
-    let command_args = &conf.start_cmd;
-    let (command, params) = command_args.split_first().unwrap();
     let svc_name = &(conf.name);
-    let mut current_dir = env::ROOT_DIR.clone();
-    current_dir.push(&conf.name);
-    let real_cmd = if command.starts_with(".") {
-        let mut abs_command = current_dir.clone();
-        //同时去掉command路径中的"./"部分，不然push会导致错误
-        abs_command.push(&command[2..]);
-        abs_command
-    } else {
-        PathBuf::from(command)
+    let (mut cmd, output) = match prepare_command(&conf) {
+        Ok(prepared) => prepared,
+        Err(err) => {
+            let err_msg = format!("prepare start command error: {}", err);
+            status::update_proc_to_spawn_failed(svc_name, &err_msg)?;
+            return Err(Error::msg(err_msg));
+        }
     };
-    let mut cmd = Command::new(real_cmd.clone());
-    cmd.args(params);
-    //设置子进程的工作目录，这会影响子进程中对相对路径的处理,但对于全局命令来说设置可能会导致错误
-    if !real_cmd.is_relative() {
-        cmd.current_dir(current_dir);
-    }
-    if conf.log_redirect {
-        let log_file = env::create_service_redirect_log_file(svc_name, "out").unwrap();
-        let log_file_err = log_file.try_clone()?;
-        cmd.stdout(Stdio::from(log_file));
-        cmd.stderr(Stdio::from(log_file_err));
-    } else {
-        let log_file_err = env::create_service_redirect_log_file(svc_name, "err").unwrap();
-        cmd.stdout(Stdio::null());
-        cmd.stderr(log_file_err);
-    }
-    if command.starts_with(".") {}
-    before_exec(&mut cmd)?;
-    debug!("execute [{}] start command:{}", svc_name, command);
-    let child = cmd.spawn().map_err(|e| format!("{}", e));
+    //健康状态从本次运行重新开始计算，log类型的检查可能在进程启动后立即匹配成功
+    status::update_proc_runtime(svc_name, |p| {
+        p.health = None;
+        p.health_message = None;
+        p.readiness = Default::default();
+        p.liveness = Default::default();
+    })?;
+    let child = cmd.spawn().map_err(|e| {
+        format!(
+            "{} (working dir: {}): {}",
+            cmd.get_program().to_string_lossy(),
+            cmd.get_current_dir().unwrap_or(Path::new("")).display(),
+            e
+        )
+    });
     match child {
         Ok(mut child_proc) => {
+            output.attach(&mut child_proc);
             //更新进程状态为已启动
             status::update_proc_to_started(svc_name, child_proc.id(), true)?;
+            if conf.post_start.is_some() {
+                let conf = Arc::clone(&conf);
+                thread::spawn(move || {
+                    //post_start钩子执行失败时停止刚启动的服务
+                    if hook::run_hook(&conf, HookPoint::PostStart).is_err() {
+                        stop_service(&conf.name).unwrap_or_else(|e| {
+                            error!("[{}] stop after post_start failed: {}", conf.name, e)
+                        });
+                    }
+                });
+            }
             let exit_status = child_proc.wait().map_err(|e| format!("{}", e));
+            //进程退出后，在记录退出状态前执行post_stop钩子，失败时只记录日志
+            let _ = hook::run_hook(&conf, HookPoint::PostStop);
             match exit_status {
                 Ok(status) => {
-                    //进程正常退出
+                    //进程正常退出，被信号终止的进程没有退出码
+                    let exit_msg = status.code().map_or_else(
+                        || "terminated by signal".to_string(),
+                        |code| format!("exit code: {}", code),
+                    );
                     status::update_proc_to_stopped(
                         svc_name,
-                        format!("exit code: {}", status.code().or(Some(0)).unwrap()).as_str(),
+                        exit_msg.as_str(),
+                        status.code(),
                         child_proc.id(),
                     )?;
                 }
                 Err(err) => {
                     //进程异常退出
-                    status::update_proc_to_stopped(svc_name, err.as_str(), child_proc.id())?;
+                    status::update_proc_to_stopped(svc_name, err.as_str(), None, child_proc.id())?;
                 }
             }
         }
         Err(err) => {
-            return Err(Error::msg(format!("spawn process error: {}", err)));
+            let err_msg = format!("spawn process error: {}", err);
+            status::update_proc_to_spawn_failed(svc_name, &err_msg)?;
+            return Err(Error::msg(err_msg));
         }
     }
     Ok(())
 }
 
+//构建服务的启动命令，包括工作目录、环境变量以及输出重定向
+fn prepare_command(conf: &ServiceConfig) -> Result<(Command, OutputCapture)> {
+    if conf.start_cmd.is_empty() {
+        return Err(Error::msg("start_cmd is empty"));
+    }
+    let svc_name = &(conf.name);
+    let mut cmd = exec::build_command(conf, &conf.start_cmd)?;
+    let output = OutputCapture::redirect(conf, &mut cmd)?;
+    before_exec(&mut cmd)?;
+    debug!(
+        "execute [{}] start command:{} in {}",
+        svc_name,
+        cmd.get_program().to_string_lossy(),
+        cmd.get_current_dir().unwrap_or(Path::new("")).display()
+    );
+    Ok((cmd, output))
+}
+
 #[cfg(test)]
 mod tests {
     use log::LevelFilter;
@@ -207,14 +500,11 @@ mod tests {
         let mut services_map = HashMap::new();
         let service_config = ServiceConfig {
             name: "service1".to_string(),
-            log_redirect: false,
-            log_pattern: None,
-            healthcheck: None,
             start_cmd: "timeout /t 10"
                 .split_whitespace()
                 .map(|s| s.to_string())
                 .collect(),
-            depends_on: None,
+            ..Default::default()
         };
         services_map.insert("service1".to_string(), service_config);
         let global_config = GlobalConfig {
@@ -273,4 +563,41 @@ mod tests {
         assert_eq!(service_info.last_stop_time.is_some(), true);
         assert_eq!(service_info.stopped_by_supervisor, true);
     }
+
+    #[test]
+    fn test_find_dependents() {
+        let instance = |service: &str, index: u32, deps: Vec<&str>| {
+            let config = ServiceConfig {
+                name: service.to_string(),
+                depends_on: Some(
+                    deps.iter()
+                        .map(|d| (d.to_string(), Default::default()))
+                        .collect(),
+                ),
+                ..Default::default()
+            };
+            let config = config::instance_config(&config, index).unwrap();
+            ProcessRuntimeInfo {
+                service: service.to_string(),
+                replica_index: index,
+                ..ProcessRuntimeInfo::new(&config.name.clone(), Arc::new(config))
+            }
+        };
+        let services = vec![
+            instance("db", 0, vec![]),
+            instance("cache", 0, vec![]),
+            instance("cache", 1, vec![]),
+            instance("app", 0, vec!["db", "cache"]),
+            instance("worker", 0, vec!["db", "removed"]),
+        ];
+        let dependents = find_dependents(&services);
+        let mut db_dependents = dependents["db"].clone();
+        db_dependents.sort();
+        assert_eq!(db_dependents, vec!["app", "worker"]);
+        assert_eq!(dependents["cache"], vec!["app"]);
+        //服务的每个实例都要等待依赖它的服务停止
+        assert_eq!(dependents["cache-1"], vec!["app"]);
+        assert!(!dependents.contains_key("app"));
+        assert!(!dependents.contains_key("removed"));
+    }
 }
@@ -1,4 +1,9 @@
+pub mod exec;
+pub mod hook;
 pub mod manager;
+pub mod output;
 pub mod pending;
 mod platform;
+pub mod restart;
+pub mod schedule;
 pub mod status;
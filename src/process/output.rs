@@ -0,0 +1,806 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender, TrySendError},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Error, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local, Utc,
+};
+use flate2::{write::GzEncoder, Compression};
+use log::warn;
+use regex::Regex;
+
+use super::status;
+use crate::{
+    config::{LogRotationConfig, ServiceConfig},
+    console::{self, ConsolePrinter},
+    env, health,
+};
+
+const MB: u64 = 1024 * 1024;
+
+//按天及大小切分的日志文件：当天的日志追加写入{prefix}_{日期}.log，
+//超过大小限制时切分为{prefix}_{日期}.{序号}.log，日期变化后写入新的文件
+pub(crate) struct RotatingFile {
+    dir: PathBuf,
+    prefix: String,
+    max_size: Option<u64>,
+    policy: LogRotationConfig,
+    file: File,
+    date: String,
+    size: u64,
+    //当前写入的文件，后台清理时据此排除，切分后随之更新
+    active: Arc<RwLock<PathBuf>>,
+    compressor: Option<Compressor>,
+}
+
+impl RotatingFile {
+    pub(crate) fn open(dir: &Path, prefix: &str, policy: LogRotationConfig) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let date = today();
+        let active = dir.join(format!("{}_{}.log", prefix, date));
+        let (file, size) = open_append(&active)?;
+        let mut rotating_file = RotatingFile {
+            dir: dir.to_path_buf(),
+            prefix: prefix.to_string(),
+            max_size: policy.max_size.map(|m| m * MB),
+            policy,
+            file,
+            date,
+            size,
+            active: Arc::new(RwLock::new(active)),
+            compressor: None,
+        };
+        //上次运行留下的未压缩文件(如重启前一天的日志)，压缩后再清理
+        let leftovers = if rotating_file.policy.compress {
+            rotating_file.uncompressed_files()
+        } else {
+            Vec::new()
+        };
+        if leftovers.is_empty() {
+            rotating_file.cleanup();
+        }
+        for path in leftovers {
+            rotating_file.compress(path);
+        }
+        Ok(rotating_file)
+    }
+
+    fn active_path(&self) -> PathBuf {
+        self.dir.join(format!("{}_{}.log", self.prefix, self.date))
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let (file, size) = open_append(&self.active_path())?;
+        self.file = file;
+        self.size = size;
+        *self.active.write().unwrap() = self.active_path();
+        Ok(())
+    }
+
+    //日期变化或文件超过大小限制时切分
+    fn rotate_if_needed(&mut self, date: &str) -> io::Result<()> {
+        let rotated = if date != self.date {
+            let previous = self.active_path();
+            self.date = date.to_string();
+            self.reopen()?;
+            previous
+        } else if self.max_size.is_some_and(|max_size| self.size >= max_size) {
+            let rotated = self.next_rotated_path();
+            fs::rename(self.active_path(), &rotated)?;
+            self.reopen()?;
+            rotated
+        } else {
+            return Ok(());
+        };
+        if self.policy.compress {
+            self.compress(rotated);
+        } else {
+            self.cleanup();
+        }
+        Ok(())
+    }
+
+    //压缩完成后再清理，保证按压缩后的文件计算保留数量
+    fn compress(&mut self, rotated: PathBuf) {
+        let compressor = self.compressor.get_or_insert_with(|| {
+            Compressor::start(
+                &self.dir,
+                &self.prefix,
+                self.policy.clone(),
+                self.active.clone(),
+            )
+        });
+        compressor.submit(rotated);
+    }
+
+    fn uncompressed_files(&self) -> Vec<PathBuf> {
+        let active = self.active_path();
+        match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| *p != active && is_log_file(&self.prefix, p))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "log"))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn next_rotated_path(&self) -> PathBuf {
+        let mut seq = 1;
+        loop {
+            let path = self
+                .dir
+                .join(format!("{}_{}.{}.log", self.prefix, self.date, seq));
+            if !path.exists() && !gz_path(&path).exists() {
+                return path;
+            }
+            seq += 1;
+        }
+    }
+
+    fn cleanup(&self) {
+        cleanup_rotated(&self.dir, &self.prefix, &self.policy, &self.active_path());
+    }
+}
+
+impl Drop for RotatingFile {
+    fn drop(&mut self) {
+        if let Some(compressor) = self.compressor.take() {
+            compressor.finish();
+        }
+    }
+}
+
+//后台压缩已切分的日志文件，避免压缩较大的文件时阻塞服务输出的读取；
+//同一个日志文件的压缩及清理在同一个线程中按顺序执行
+struct Compressor {
+    sender: Sender<PathBuf>,
+    handle: JoinHandle<()>,
+}
+
+impl Compressor {
+    fn start(
+        dir: &Path,
+        prefix: &str,
+        policy: LogRotationConfig,
+        active: Arc<RwLock<PathBuf>>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel::<PathBuf>();
+        let dir = dir.to_path_buf();
+        let prefix = prefix.to_string();
+        let handle = thread::spawn(move || {
+            for rotated in receiver {
+                if let Err(err) = compress_file(&rotated) {
+                    warn!("compress log file {} failed: {}", rotated.display(), err);
+                }
+                //压缩期间可能已再次切分，按清理时正在写入的文件排除
+                let active = active.read().unwrap().clone();
+                cleanup_rotated(&dir, &prefix, &policy, &active);
+            }
+        });
+        Compressor { sender, handle }
+    }
+
+    fn submit(&self, rotated: PathBuf) {
+        if self.sender.send(rotated).is_err() {
+            warn!("log compressor has been stopped");
+        }
+    }
+
+    //等待已提交的压缩任务全部完成
+    fn finish(self) {
+        drop(self.sender);
+        let _ = self.handle.join();
+    }
+}
+
+//按照max_files及retention_days删除已切分的日志文件
+fn cleanup_rotated(dir: &Path, prefix: &str, policy: &LogRotationConfig, active: &Path) {
+    if policy.max_files.is_none() && policy.retention_days.is_none() {
+        return;
+    }
+    let mut rotated: Vec<(SystemTime, PathBuf)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p != active && is_log_file(prefix, p))
+            .filter_map(|p| Some((fs::metadata(&p).ok()?.modified().ok()?, p)))
+            .collect(),
+        Err(_) => return,
+    };
+    //最新的文件排在前面
+    rotated.sort_by(|a, b| b.cmp(a));
+    let expire_time = policy
+        .retention_days
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(days * 86400)));
+    for (i, (modified, path)) in rotated.iter().enumerate() {
+        let over_limit = policy.max_files.is_some_and(|max| i >= max);
+        let expired = expire_time.is_some_and(|t| *modified < t);
+        if over_limit || expired {
+            fs::remove_file(path)
+                .unwrap_or_else(|e| warn!("remove log file {} failed: {}", path.display(), e));
+        }
+    }
+}
+
+fn is_log_file(prefix: &str, path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with(&format!("{}_", prefix))
+        && (name.ends_with(".log") || name.ends_with(".log.gz"))
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.rotate_if_needed(&today())?;
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn today() -> String {
+    Utc::now().format("%Y%m%d").to_string()
+}
+
+fn open_append(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+//压缩后的文件保留原文件的修改时间，清理时按原文件的时间排序
+fn compress_file(path: &Path) -> io::Result<()> {
+    let mut source = File::open(path)?;
+    let modified = source.metadata()?.modified()?;
+    let mut encoder = GzEncoder::new(File::create(gz_path(path))?, Compression::default());
+    io::copy(&mut source, &mut encoder)?;
+    encoder.finish()?.set_modified(modified)?;
+    fs::remove_file(path)
+}
+
+//读取的输出行缓存上限，日志写入跟不上时丢弃新的输出行，保证服务进程不会因为管道写满而阻塞
+const LINE_BUFFER_SIZE: usize = 1024;
+//单行输出的最大长度，超过时拆分为多行
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stream::Stdout => f.write_str("out"),
+            Stream::Stderr => f.write_str("err"),
+        }
+    }
+}
+
+//服务输出的一行内容
+#[derive(Clone, Debug)]
+pub(crate) struct OutputLine {
+    pub(crate) stream: Stream,
+    pub(crate) pid: u32,
+    pub(crate) time: DateTime<Local>,
+    pub(crate) text: String,
+}
+
+pub(crate) const DEFAULT_LINE_PATTERN: &str = "{d} [{stream}] {m}";
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+#[derive(Clone, Debug, PartialEq)]
+enum PatternItem {
+    Literal(String),
+    Date(String),
+    Service,
+    Stream,
+    Pid,
+    Message,
+}
+
+//服务输出行的格式(log_pattern)，占位符参考log4rs：{d}或{d(日期格式)}、{service}、{stream}、{pid}、{m}，{{和}}表示字面量的花括号
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LinePattern {
+    items: Vec<PatternItem>,
+}
+
+impl LinePattern {
+    pub(crate) fn parse(pattern: &str) -> Result<Self> {
+        let mut items = Vec::new();
+        let mut literal = String::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    //日期格式中可能包含花括号以外的任意字符，直到匹配的右括号为止
+                    let mut depth = 0;
+                    loop {
+                        match chars.next() {
+                            Some('}') if depth == 0 => break,
+                            Some(c) => {
+                                if c == '(' {
+                                    depth += 1;
+                                } else if c == ')' {
+                                    depth -= 1;
+                                }
+                                name.push(c);
+                            }
+                            None => {
+                                return Err(Error::msg(format!(
+                                    "unclosed placeholder in \"{}\"",
+                                    pattern
+                                )))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        items.push(PatternItem::Literal(std::mem::take(&mut literal)));
+                    }
+                    items.push(parse_placeholder(&name)?);
+                }
+                '}' => {
+                    return Err(Error::msg(format!(
+                        "unmatched '}}' in \"{}\", use '}}}}' for a literal brace",
+                        pattern
+                    )))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            items.push(PatternItem::Literal(literal));
+        }
+        Ok(LinePattern { items })
+    }
+
+    //服务未配置log_pattern时使用默认格式
+    pub(crate) fn from_config(conf: &ServiceConfig) -> Result<Self> {
+        LinePattern::parse(conf.log_pattern.as_deref().unwrap_or(DEFAULT_LINE_PATTERN))
+    }
+
+    pub(crate) fn format(&self, service_name: &str, line: &OutputLine) -> String {
+        let mut result = String::new();
+        for item in self.items.iter() {
+            match item {
+                PatternItem::Literal(text) => result.push_str(text),
+                PatternItem::Date(format) => result.push_str(&line.time.format(format).to_string()),
+                PatternItem::Service => result.push_str(service_name),
+                PatternItem::Stream => result.push_str(&line.stream.to_string()),
+                PatternItem::Pid => result.push_str(&line.pid.to_string()),
+                PatternItem::Message => result.push_str(&line.text),
+            }
+        }
+        result.push('\n');
+        result
+    }
+}
+
+fn parse_placeholder(name: &str) -> Result<PatternItem> {
+    let item = match name {
+        "d" => PatternItem::Date(DEFAULT_DATE_FORMAT.to_string()),
+        "service" => PatternItem::Service,
+        "stream" => PatternItem::Stream,
+        "pid" => PatternItem::Pid,
+        "m" => PatternItem::Message,
+        _ => {
+            let format = name
+                .strip_prefix("d(")
+                .and_then(|f| f.strip_suffix(')'))
+                .ok_or_else(|| Error::msg(format!("unknown placeholder {{{}}}", name)))?;
+            if StrftimeItems::new(format).any(|i| i == Item::Error) {
+                return Err(Error::msg(format!("invalid date format \"{}\"", format)));
+            }
+            PatternItem::Date(format.to_string())
+        }
+    };
+    Ok(item)
+}
+
+//由process-compose接管服务进程的输出：进程的输出通过管道按行读取，加上时间及来源标记后写入服务的日志文件
+pub(crate) struct OutputCapture {
+    capture_stdout: bool,
+    pid: u32,
+    sender: SyncSender<OutputLine>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl OutputCapture {
+    //开启log_redirect时标准输出及错误输出都写入out日志，否则只将错误输出写入err日志；
+    //up模式下所有输出同时输出到终端
+    pub(crate) fn redirect(conf: &ServiceConfig, cmd: &mut Command) -> Result<Self> {
+        let dir = env::get_service_log_dir(&conf.name);
+        let prefix = if conf.log_redirect { "out" } else { "err" };
+        let service = status::find_readonly_proc_runtime(&conf.name)
+            .map(|p| p.service)
+            .unwrap_or_else(|_| conf.name.clone());
+        let writer = LineWriter {
+            service_name: conf.name.clone(),
+            pattern: LinePattern::from_config(conf)?,
+            file: RotatingFile::open(&dir, prefix, conf.log_rotation.clone())?,
+            log_stdout: conf.log_redirect,
+            console: console::printer(&conf.name, &service),
+            log_matcher: health::log_matcher(conf),
+            write_failed: false,
+        };
+        let capture_stdout =
+            writer.log_stdout || writer.console.is_some() || writer.log_matcher.is_some();
+        let (sender, receiver) = mpsc::sync_channel(LINE_BUFFER_SIZE);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer_dropped = Arc::clone(&dropped);
+        thread::spawn(move || write_lines(receiver, &writer_dropped, writer));
+        if capture_stdout {
+            cmd.stdout(Stdio::piped());
+        } else {
+            cmd.stdout(Stdio::null());
+        }
+        cmd.stderr(Stdio::piped());
+        Ok(OutputCapture {
+            capture_stdout,
+            pid: 0,
+            sender,
+            dropped,
+        })
+    }
+
+    //进程启动后开始读取其输出
+    pub(crate) fn attach(mut self, child: &mut Child) {
+        self.pid = child.id();
+        if let Some(pipe) = child.stdout.take().filter(|_| self.capture_stdout) {
+            self.read_in_background(pipe, Stream::Stdout);
+        }
+        if let Some(pipe) = child.stderr.take() {
+            self.read_in_background(pipe, Stream::Stderr);
+        }
+    }
+
+    fn read_in_background<R>(&self, pipe: R, stream: Stream)
+    where
+        R: Read + Send + 'static,
+    {
+        let sender = self.sender.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let pid = self.pid;
+        thread::spawn(move || {
+            read_lines(pipe, stream, pid, |line| {
+                if let Err(TrySendError::Full(_)) = sender.try_send(line) {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        });
+    }
+}
+
+//管道关闭(进程退出)前持续按行读取，非UTF-8的内容按替换字符处理，末尾不完整的行同样输出
+fn read_lines<R: Read, F: FnMut(OutputLine)>(pipe: R, stream: Stream, pid: u32, mut emit: F) {
+    let mut reader = BufReader::new(pipe);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match (&mut reader)
+            .take(MAX_LINE_BYTES)
+            .read_until(b'\n', &mut buf)
+        {
+            Ok(0) => break,
+            Ok(_) => {
+                let text = String::from_utf8_lossy(&buf);
+                emit(OutputLine {
+                    stream,
+                    pid,
+                    time: Local::now(),
+                    text: text.trim_end_matches(['\n', '\r']).to_string(),
+                });
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+}
+
+//输出行的去向：服务的日志文件以及up模式下的终端
+struct LineWriter<W: Write> {
+    service_name: String,
+    pattern: LinePattern,
+    file: W,
+    //未开启log_redirect时标准输出只输出到终端
+    log_stdout: bool,
+    console: Option<ConsolePrinter>,
+    //log类型的健康检查，匹配成功后不再检查
+    log_matcher: Option<Regex>,
+    write_failed: bool,
+}
+
+impl<W: Write> LineWriter<W> {
+    fn write(&mut self, line: &OutputLine) {
+        if self
+            .log_matcher
+            .as_ref()
+            .is_some_and(|re| re.is_match(&line.text))
+        {
+            health::on_log_matched(&self.service_name, &line.text);
+            self.log_matcher = None;
+        }
+        if let Some(console) = self.console.as_ref() {
+            console.print(&line.text);
+        }
+        if line.stream == Stream::Stdout && !self.log_stdout {
+            return;
+        }
+        let content = self.pattern.format(&self.service_name, line);
+        match self.file.write_all(content.as_bytes()) {
+            Err(err) if !self.write_failed => {
+                warn!("[{}] write service log failed: {}", self.service_name, err);
+                self.write_failed = true;
+            }
+            Ok(_) => self.write_failed = false,
+            _ => {}
+        }
+    }
+}
+
+//所有读取线程结束(进程退出)后写入线程随之退出
+fn write_lines<W: Write>(
+    receiver: Receiver<OutputLine>,
+    dropped: &AtomicU64,
+    mut writer: LineWriter<W>,
+) {
+    for line in receiver {
+        writer.write(&line);
+        //在当前行之后标记写入跟不上时被丢弃的行数
+        let dropped_lines = dropped.swap(0, Ordering::Relaxed);
+        if dropped_lines > 0 {
+            writer.write(&OutputLine {
+                text: format!("[process-compose] {} lines dropped", dropped_lines),
+                ..line
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_rotate_by_size_and_keep_max_files() {
+        let dir = test_dir("process-compose-rotate-size-test");
+        let policy = LogRotationConfig {
+            max_files: Some(2),
+            compress: true,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::open(&dir, "out", policy).unwrap();
+        file.max_size = Some(10);
+        for _ in 0..3 {
+            file.write_all(b"0123456789").unwrap();
+        }
+        file.write_all(b"tail").unwrap();
+        //等待后台压缩完成
+        drop(file);
+        let date = today();
+        assert_eq!(
+            file_names(&dir),
+            vec![
+                format!("out_{}.2.log.gz", date),
+                format!("out_{}.3.log.gz", date),
+                format!("out_{}.log", date),
+            ]
+        );
+        let content = fs::read_to_string(dir.join(format!("out_{}.log", date))).unwrap();
+        assert_eq!(content, "tail");
+    }
+
+    #[test]
+    fn test_rotate_by_date_and_append() {
+        let dir = test_dir("process-compose-rotate-date-test");
+        let mut file = RotatingFile::open(&dir, "err", LogRotationConfig::default()).unwrap();
+        file.write_all(b"first\n").unwrap();
+        //重新打开时追加写入，而不是清空当天的日志
+        let mut file = RotatingFile::open(&dir, "err", LogRotationConfig::default()).unwrap();
+        file.write_all(b"second\n").unwrap();
+        let today_path = dir.join(format!("err_{}.log", today()));
+        assert_eq!(fs::read_to_string(&today_path).unwrap(), "first\nsecond\n");
+        file.rotate_if_needed("20990101").unwrap();
+        file.file.write_all(b"next day\n").unwrap();
+        let next_day = fs::read_to_string(dir.join("err_20990101.log")).unwrap();
+        assert_eq!(next_day, "next day\n");
+        assert!(today_path.exists());
+    }
+
+    #[test]
+    fn test_compress_leftovers_on_open() {
+        let dir = test_dir("process-compose-rotate-leftover-test");
+        fs::create_dir_all(&dir).unwrap();
+        //重启前一天未压缩的日志及中断压缩的切分文件
+        fs::write(dir.join("out_20000101.log"), "yesterday").unwrap();
+        fs::write(dir.join("out_20000101.1.log"), "rotated").unwrap();
+        let policy = LogRotationConfig {
+            compress: true,
+            ..Default::default()
+        };
+        drop(RotatingFile::open(&dir, "out", policy).unwrap());
+        assert_eq!(
+            file_names(&dir),
+            vec![
+                "out_20000101.1.log.gz".to_string(),
+                "out_20000101.log.gz".to_string(),
+                format!("out_{}.log", today()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cleanup_keeps_active_file_after_rotations() {
+        let dir = test_dir("process-compose-rotate-active-test");
+        let policy = LogRotationConfig {
+            max_files: Some(1),
+            compress: true,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::open(&dir, "err", policy).unwrap();
+        file.write_all(b"today\n").unwrap();
+        file.rotate_if_needed("20990101").unwrap();
+        file.file.write_all(b"first\n").unwrap();
+        file.rotate_if_needed("20990102").unwrap();
+        file.file.write_all(b"second\n").unwrap();
+        drop(file);
+        assert_eq!(
+            file_names(&dir),
+            vec!["err_20990101.log.gz", "err_20990102.log"]
+        );
+        let active = fs::read_to_string(dir.join("err_20990102.log")).unwrap();
+        assert_eq!(active, "second\n");
+    }
+
+    #[test]
+    fn test_read_lines_with_partial_and_binary_output() {
+        let input: &[u8] = b"first\r\nbin\xffary\n\npartial";
+        let mut lines = Vec::new();
+        read_lines(input, Stream::Stderr, 42, |line| lines.push(line));
+        let texts: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["first", "bin\u{FFFD}ary", "", "partial"]);
+        assert!(lines.iter().all(|l| l.stream == Stream::Stderr));
+    }
+
+    #[test]
+    fn test_write_lines_reports_dropped_lines() {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let dropped = AtomicU64::new(0);
+        let line = |text: &str| OutputLine {
+            stream: Stream::Stdout,
+            pid: 42,
+            time: Local::now(),
+            text: text.to_string(),
+        };
+        sender.try_send(line("kept")).unwrap();
+        //缓存已满，写入线程跟不上时新的行被丢弃
+        assert!(matches!(
+            sender.try_send(line("lost")),
+            Err(TrySendError::Full(_))
+        ));
+        dropped.fetch_add(1, Ordering::Relaxed);
+        drop(sender);
+        let mut output = Vec::new();
+        let writer = LineWriter {
+            service_name: "svc".to_string(),
+            pattern: LinePattern::parse(DEFAULT_LINE_PATTERN).unwrap(),
+            file: &mut output,
+            log_stdout: true,
+            console: None,
+            log_matcher: None,
+            write_failed: false,
+        };
+        write_lines(receiver, &dropped, writer);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("[out] kept"));
+        assert!(lines[1].ends_with("[out] [process-compose] 1 lines dropped"));
+    }
+
+    #[test]
+    fn test_log_matcher_marks_ready() {
+        let name = "log-match-test";
+        let conf = ServiceConfig {
+            name: name.to_string(),
+            readiness: serde_yaml::from_str("{test_type: log, test_target: 'listening on \\d+'}")
+                .unwrap(),
+            ..Default::default()
+        };
+        status::add_test_process(conf.clone());
+        let mut output = Vec::new();
+        let mut writer = LineWriter {
+            service_name: name.to_string(),
+            pattern: LinePattern::parse(DEFAULT_LINE_PATTERN).unwrap(),
+            file: &mut output,
+            log_stdout: true,
+            console: None,
+            log_matcher: health::log_matcher(&conf),
+            write_failed: false,
+        };
+        let line = |text: &str| OutputLine {
+            stream: Stream::Stdout,
+            pid: 42,
+            time: Local::now(),
+            text: text.to_string(),
+        };
+        writer.write(&line("starting"));
+        assert!(writer.log_matcher.is_some());
+        writer.write(&line("listening on 8080"));
+        //匹配成功后不再检查
+        assert!(writer.log_matcher.is_none());
+        let readiness = status::find_readonly_proc_runtime(name).unwrap().readiness;
+        assert_eq!(readiness.successes, 1);
+    }
+
+    #[test]
+    fn test_line_pattern() {
+        let line = OutputLine {
+            stream: Stream::Stderr,
+            pid: 42,
+            time: Local.with_ymd_and_hms(2024, 5, 1, 8, 30, 0).unwrap(),
+            text: "boom".to_string(),
+        };
+        let pattern = LinePattern::parse("{d(%H:%M)} {service}[{pid}] {{{stream}}} {m}").unwrap();
+        assert_eq!(pattern.format("api", &line), "08:30 api[42] {err} boom\n");
+        let pattern = LinePattern::parse(DEFAULT_LINE_PATTERN).unwrap();
+        assert_eq!(
+            pattern.format("api", &line),
+            "2024-05-01 08:30:00.000 [err] boom\n"
+        );
+        assert!(LinePattern::parse("{level} {m}").is_err());
+        assert!(LinePattern::parse("{d(%Q)} {m}").is_err());
+        assert!(LinePattern::parse("{m").is_err());
+        assert!(LinePattern::parse("m}").is_err());
+    }
+}
@@ -20,11 +20,16 @@ pub(crate) fn add_pending_service(name: &str, depends: Vec<String>) {
     }));
 }
 
-fn remove_pending_service(name: &str) {
+pub(crate) fn remove_pending_service(name: &str) {
     let mut pending_list = PENDING_SERVICES.write().unwrap();
     pending_list.retain(|s| s.read().unwrap().name != name);
 }
 
+pub(crate) fn is_pending(name: &str) -> bool {
+    let pending_list = PENDING_SERVICES.read().unwrap();
+    pending_list.iter().any(|s| s.read().unwrap().name == name)
+}
+
 pub fn try_start_pending_service() {
     let pending_list = PENDING_SERVICES.read().unwrap();
     let mut started_services: Vec<String> = Vec::new();
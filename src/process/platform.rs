@@ -14,6 +14,7 @@ pub mod windows {
         AttachConsole, FreeConsole, GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT, CTRL_C_EVENT,
     };
 
+    use crate::config::StopSignal;
     use crate::env::is_run_as_service;
 
     pub fn before_exec(cmd: &mut Command) -> Result<()> {
@@ -21,7 +22,8 @@ pub mod windows {
         Ok(())
     }
 
-    pub fn terminate_process(pid: u32) -> Result<()> {
+    //windows下无法发送指定的信号，统一模拟CTRL_BREAK和CTRL_C事件
+    pub fn terminate_process(pid: u32, _signal: StopSignal) -> Result<()> {
         //如果不指定/F参数，无法终止某个进程的子进程，而process-compose的所有服务都是子进程，所以这里无法
         //使用taskkill的方式来进行信号通知
         //kill_proc(pid, false)?;
@@ -124,7 +126,7 @@ pub mod windows {
             // // 等待一段时间以确保新进程已经启动
             thread::sleep(Duration::from_secs(1));
             // 尝试杀死新进程
-            match terminate_process(pid) {
+            match terminate_process(pid, StopSignal::Term) {
                 Ok(_) => println!("Successfully killed process with PID: {}", pid),
                 Err(e) => println!("Failed to kill process with PID: {}. Error: {}", pid, e),
             };
@@ -146,6 +148,8 @@ pub mod linux {
     use std::os::unix::process::CommandExt;
     use std::process::Command;
 
+    use crate::config::StopSignal;
+
     pub fn before_exec(cmd: &mut Command) -> Result<()> {
         // 在 Unix 平台上，设置新进程的进程组ID与其进程ID相同，这样它就会成为新的进程组的领导者。
         unsafe {
@@ -157,10 +161,19 @@ pub mod linux {
         Ok(())
     }
 
-    pub fn terminate_process(pid: u32) -> Result<()> {
+    pub fn terminate_process(pid: u32, signal: StopSignal) -> Result<()> {
+        let signal = match signal {
+            StopSignal::Term => Signal::SIGTERM,
+            StopSignal::Int => Signal::SIGINT,
+            StopSignal::Quit => Signal::SIGQUIT,
+            StopSignal::Hup => Signal::SIGHUP,
+            StopSignal::Kill => Signal::SIGKILL,
+            StopSignal::Usr1 => Signal::SIGUSR1,
+            StopSignal::Usr2 => Signal::SIGUSR2,
+        };
         pid.try_into()
             .map_err(|_| anyhow!("PID out of range"))
-            .and_then(|pid| signal_proc(pid, Signal::SIGTERM))
+            .and_then(|pid| signal_proc(pid, signal))
     }
 
     pub fn kill_process(pid: u32) -> Result<()> {
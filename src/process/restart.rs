@@ -0,0 +1,225 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use log::{error, info};
+
+use super::{manager, status, status::ProcessRuntimeInfo};
+use crate::config::{RestartBackoffConfig, RestartPolicy, ServiceType};
+
+//等待执行的重启任务，序号用于区分同一服务先后安排的重启
+static PENDING_RESTARTS: RwLock<Vec<(String, u64)>> = RwLock::new(Vec::new());
+static RESTART_SEQ: AtomicU64 = AtomicU64::new(0);
+
+//根据服务的重启策略决定是否需要重启自行退出的服务，返回是否安排了重启
+pub fn on_service_exited(service_name: &str) -> bool {
+    let proc_runtime = match status::find_readonly_proc_runtime(service_name) {
+        Ok(proc_runtime) => proc_runtime,
+        Err(err) => {
+            error!("[{}] can not decide restart: {}", service_name, err);
+            return false;
+        }
+    };
+    //定时服务的每次运行由调度决定，不参与自动重启
+    if proc_runtime.config.schedule.is_some() {
+        return false;
+    }
+    if !need_restart(&proc_runtime) {
+        //不再重启的一次性任务失败后进入Failed状态，依赖它的服务保持等待
+        if proc_runtime.config.service_type == ServiceType::Oneshot {
+            let reason = proc_runtime.exit_err.clone().unwrap_or_default();
+            status::update_proc_to_failed(service_name, &reason).unwrap_or_else(|err| {
+                error!("[{}] mark as failed error: {}", service_name, err);
+            });
+        }
+        return false;
+    }
+    let config = &proc_runtime.config;
+    let now = SystemTime::now();
+    let window = Duration::from_secs(config.restart_window.max(0) as u64);
+    let mut history = recent_restarts(&proc_runtime.restart_history, now, window);
+    //时间窗口内的重启次数已用完，说明服务处于崩溃循环中
+    if let Some(max_restarts) = config.max_restarts {
+        if history.len() as u32 >= max_restarts {
+            let reason = format!(
+                "restarted {} times within {}s, last exit: {}",
+                history.len(),
+                config.restart_window,
+                proc_runtime.exit_err.clone().unwrap_or_default()
+            );
+            status::update_proc_to_failed(service_name, &reason).unwrap_or_else(|err| {
+                error!("[{}] mark as failed error: {}", service_name, err);
+            });
+            return false;
+        }
+    }
+    history.push(now);
+    let backoff = &config.restart_backoff;
+    //进程稳定运行超过最大退避时间后，重新从初始延迟开始计算
+    let attempts = if ran_longer_than(&proc_runtime, backoff.max_delay) {
+        0
+    } else {
+        proc_runtime.restart_attempts
+    };
+    let delay = backoff_delay(backoff, attempts);
+    if let Err(err) = status::update_proc_runtime(service_name, |p| {
+        p.restart_attempts = attempts + 1;
+        p.restart_count += 1;
+        p.restart_history = history.clone();
+    }) {
+        error!("[{}] update restart attempts failed: {}", service_name, err);
+        return false;
+    }
+    info!(
+        "[{}] will be restarted in {:?} according to restart policy {:?} (attempt {})",
+        service_name,
+        delay,
+        proc_runtime.config.restart,
+        attempts + 1
+    );
+    let seq = RESTART_SEQ.fetch_add(1, Ordering::SeqCst);
+    PENDING_RESTARTS
+        .write()
+        .unwrap()
+        .push((service_name.to_string(), seq));
+    let name = service_name.to_string();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        if !take_pending_restart(&name, seq) {
+            info!("[{}] pending restart has been cancelled", name);
+            return;
+        }
+        manager::start_service(&name).unwrap_or_else(|err| {
+            error!("[{}] restart failed: {}", name, err);
+        });
+    });
+    true
+}
+
+//取消服务尚未执行的自动重启
+pub fn cancel_restart(service_name: &str) {
+    PENDING_RESTARTS
+        .write()
+        .unwrap()
+        .retain(|(name, _)| name != service_name);
+}
+
+fn take_pending_restart(service_name: &str, seq: u64) -> bool {
+    let mut pending = PENDING_RESTARTS.write().unwrap();
+    let len = pending.len();
+    pending.retain(|(name, s)| !(name == service_name && *s == seq));
+    pending.len() != len
+}
+
+fn need_restart(proc_runtime: &ProcessRuntimeInfo) -> bool {
+    //被process-compose主动停止的服务不属于自行退出
+    if proc_runtime.stopped_by_supervisor {
+        return false;
+    }
+    match proc_runtime.config.restart {
+        RestartPolicy::No => false,
+        RestartPolicy::OnFailure => proc_runtime.exit_code != Some(0),
+        RestartPolicy::Always | RestartPolicy::UnlessStopped => true,
+    }
+}
+
+fn ran_longer_than(proc_runtime: &ProcessRuntimeInfo, secs: i32) -> bool {
+    match (proc_runtime.last_start_time, proc_runtime.last_stop_time) {
+        (Some(start), Some(stop)) => stop
+            .duration_since(start)
+            .map(|d| d >= Duration::from_secs(secs.max(0) as u64))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn recent_restarts(history: &[SystemTime], now: SystemTime, window: Duration) -> Vec<SystemTime> {
+    history
+        .iter()
+        .filter(|t| now.duration_since(**t).map_or(true, |d| d < window))
+        .cloned()
+        .collect()
+}
+
+//第n次重启的延迟为 initial_delay * multiplier^n，且不超过max_delay
+fn backoff_delay(backoff: &RestartBackoffConfig, attempts: u32) -> Duration {
+    let initial = backoff.initial_delay.max(0) as f64;
+    let max = backoff.max_delay.max(0) as f64;
+    let delay = initial * backoff.multiplier.max(1.0).powi(attempts.min(64) as i32);
+    Duration::from_secs_f64(delay.min(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceConfig;
+    use std::sync::Arc;
+
+    fn runtime_with(restart: RestartPolicy, exit_code: Option<i32>) -> ProcessRuntimeInfo {
+        let config = ServiceConfig {
+            name: "service1".to_string(),
+            restart,
+            ..Default::default()
+        };
+        let mut proc_runtime = ProcessRuntimeInfo::new("service1", Arc::new(config));
+        proc_runtime.exit_code = exit_code;
+        proc_runtime
+    }
+
+    #[test]
+    fn test_backoff_delay_grows_until_max() {
+        let backoff = RestartBackoffConfig {
+            initial_delay: 1,
+            multiplier: 2.0,
+            max_delay: 10,
+        };
+        assert_eq!(backoff_delay(&backoff, 0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(&backoff, 1), Duration::from_secs(2));
+        assert_eq!(backoff_delay(&backoff, 3), Duration::from_secs(8));
+        assert_eq!(backoff_delay(&backoff, 4), Duration::from_secs(10));
+        assert_eq!(backoff_delay(&backoff, 1000), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_need_restart_by_policy() {
+        assert!(!need_restart(&runtime_with(RestartPolicy::No, Some(1))));
+        assert!(need_restart(&runtime_with(
+            RestartPolicy::OnFailure,
+            Some(1)
+        )));
+        assert!(need_restart(&runtime_with(RestartPolicy::OnFailure, None)));
+        assert!(!need_restart(&runtime_with(
+            RestartPolicy::OnFailure,
+            Some(0)
+        )));
+        assert!(need_restart(&runtime_with(RestartPolicy::Always, Some(0))));
+        assert!(need_restart(&runtime_with(
+            RestartPolicy::UnlessStopped,
+            Some(0)
+        )));
+    }
+
+    #[test]
+    fn test_recent_restarts_drop_expired_entries() {
+        let now = SystemTime::now();
+        let history = vec![
+            now - Duration::from_secs(120),
+            now - Duration::from_secs(30),
+            now - Duration::from_secs(1),
+        ];
+        let recent = recent_restarts(&history, now, Duration::from_secs(60));
+        assert_eq!(recent, history[1..].to_vec());
+    }
+
+    #[test]
+    fn test_no_restart_when_stopped_by_supervisor() {
+        let mut proc_runtime = runtime_with(RestartPolicy::Always, Some(1));
+        proc_runtime.stopped_by_supervisor = true;
+        assert!(!need_restart(&proc_runtime));
+    }
+}
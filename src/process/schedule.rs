@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{manager, status};
+use crate::{
+    config::{self, OverlapPolicy, ScheduleConfig},
+    env,
+};
+
+const RUN_HISTORY_FILE_NAME: &str = "runs.jsonl";
+//每个服务最多保留的运行记录数
+const MAX_RUN_HISTORY: usize = 100;
+
+lazy_static! {
+    //每个定时服务当前生效的调度线程编号及其调度配置，配置变化后旧的调度线程据此退出
+    static ref SCHEDULERS: RwLock<HashMap<String, (u64, ScheduleConfig)>> =
+        RwLock::new(HashMap::new());
+    //由计划触发且尚未结束的运行的开始时间
+    static ref SCHEDULED_RUNS: RwLock<HashMap<String, SystemTime>> = RwLock::new(HashMap::new());
+}
+
+static SCHEDULER_SEQ: AtomicU64 = AtomicU64::new(0);
+
+//一次运行的记录，保存在{app_data_home}/{service_name}/runs.jsonl中
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct RunRecord {
+    pub(crate) start: String,
+    pub(crate) end: String,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) result: String,
+}
+
+pub fn is_scheduled(service_name: &str) -> bool {
+    config::find_service_config(service_name).is_some_and(|s| s.schedule.is_some())
+}
+
+//根据当前配置启动、更新或停止各定时服务的调度线程，启动及热加载配置后调用
+pub fn sync_schedulers() {
+    let services = config::current_config().services;
+    let mut schedulers = SCHEDULERS.write().unwrap();
+    schedulers.retain(|name, (_, schedule)| {
+        services
+            .get(name)
+            .and_then(|s| s.schedule.as_ref())
+            .is_some_and(|s| s == schedule)
+    });
+    for (name, service) in services.iter() {
+        let schedule = match service.schedule.as_ref() {
+            Some(schedule) => schedule,
+            None => continue,
+        };
+        if schedulers.contains_key(name) {
+            continue;
+        }
+        let id = SCHEDULER_SEQ.fetch_add(1, Ordering::SeqCst);
+        schedulers.insert(name.clone(), (id, schedule.clone()));
+        let name = name.clone();
+        let schedule = schedule.clone();
+        thread::spawn(move || run_scheduler(name, schedule, id));
+    }
+}
+
+fn is_current_scheduler(service_name: &str, id: u64) -> bool {
+    let schedulers = SCHEDULERS.read().unwrap();
+    schedulers.get(service_name).is_some_and(|(i, _)| *i == id)
+}
+
+fn run_scheduler(service_name: String, schedule: ScheduleConfig, id: u64) {
+    info!(
+        "[{}] is scheduled by \"{}\" ({})",
+        service_name,
+        schedule.cron,
+        schedule.timezone.as_deref().unwrap_or("local time")
+    );
+    loop {
+        let next = match next_fire_time(&schedule, Utc::now()) {
+            Ok(Some(next)) => next,
+            Ok(None) => {
+                info!("[{}] has no upcoming scheduled run", service_name);
+                return;
+            }
+            Err(err) => {
+                error!("[{}] schedule error: {}", service_name, err);
+                return;
+            }
+        };
+        //分段等待，以便配置变化后尽快退出
+        loop {
+            if !is_current_scheduler(&service_name, id) {
+                return;
+            }
+            let remaining = (next - Utc::now()).to_std().unwrap_or_default();
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(Duration::from_secs(1)));
+        }
+        fire(&service_name, &schedule);
+    }
+}
+
+//计算当前时间之后的下一次运行时间
+pub(crate) fn next_fire_time(
+    schedule: &ScheduleConfig,
+    after: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>> {
+    let cron = Schedule::from_str(&schedule.cron)
+        .map_err(|e| Error::msg(format!("invalid cron expression: {}", e)))?;
+    let next = match schedule.timezone.as_ref() {
+        Some(timezone) => {
+            let tz = Tz::from_str(timezone).map_err(Error::msg)?;
+            cron.after(&after.with_timezone(&tz))
+                .next()
+                .map(|t| t.with_timezone(&Utc))
+        }
+        None => cron
+            .after(&after.with_timezone(&Local))
+            .next()
+            .map(|t| t.with_timezone(&Utc)),
+    };
+    Ok(next)
+}
+
+fn fire(service_name: &str, schedule: &ScheduleConfig) {
+    if status::is_running_by_name(service_name) {
+        match schedule.overlap {
+            OverlapPolicy::Skip => {
+                warn!(
+                    "[{}] previous run is still running, skip this scheduled run",
+                    service_name
+                );
+                return;
+            }
+            OverlapPolicy::Queue => {
+                info!(
+                    "[{}] previous run is still running, waiting for it to finish",
+                    service_name
+                );
+                while status::is_running_by_name(service_name) {
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+            OverlapPolicy::KillPrevious => {
+                warn!(
+                    "[{}] previous run is still running, stopping it",
+                    service_name
+                );
+                if let Err(err) = manager::stop_service(service_name) {
+                    error!("[{}] stop previous run failed: {}", service_name, err);
+                    return;
+                }
+                manager::wait_for_exit_recorded(service_name, Duration::from_secs(5));
+            }
+        }
+    }
+    info!("[{}] scheduled run is starting", service_name);
+    let started_at = SystemTime::now();
+    SCHEDULED_RUNS
+        .write()
+        .unwrap()
+        .insert(service_name.to_string(), started_at);
+    if let Err(err) = manager::start_service(service_name) {
+        error!("[{}] start scheduled run failed: {}", service_name, err);
+        SCHEDULED_RUNS.write().unwrap().remove(service_name);
+        return;
+    }
+    if let Some(max_runtime) = schedule.max_runtime {
+        let name = service_name.to_string();
+        thread::spawn(move || watch_max_runtime(name, started_at, max_runtime));
+    }
+}
+
+//运行时间超过max_runtime时停止本次运行
+fn watch_max_runtime(service_name: String, started_at: SystemTime, max_runtime: i32) {
+    thread::sleep(Duration::from_secs(max_runtime.max(0) as u64));
+    let still_running = SCHEDULED_RUNS.read().unwrap().get(&service_name) == Some(&started_at);
+    if still_running && status::is_running_by_name(&service_name) {
+        warn!(
+            "[{}] has been running longer than max_runtime {}s, stopping it",
+            service_name, max_runtime
+        );
+        manager::stop_service(&service_name).unwrap_or_else(|err| {
+            error!("[{}] stop scheduled run failed: {}", service_name, err);
+        });
+    }
+}
+
+//定时服务的一次运行结束后记录运行结果
+pub fn on_run_finished(service_name: &str) {
+    if !is_scheduled(service_name) {
+        return;
+    }
+    let proc_runtime = match status::find_readonly_proc_runtime(service_name) {
+        Ok(proc_runtime) => proc_runtime,
+        Err(_) => return,
+    };
+    //手动启动的运行没有计划开始时间，使用进程的启动时间
+    let start = SCHEDULED_RUNS
+        .write()
+        .unwrap()
+        .remove(service_name)
+        .or(proc_runtime.last_start_time)
+        .unwrap_or_else(SystemTime::now);
+    let format_time = |t: SystemTime| DateTime::<Local>::from(t).to_rfc3339();
+    let record = RunRecord {
+        start: format_time(start),
+        end: format_time(proc_runtime.last_stop_time.unwrap_or_else(SystemTime::now)),
+        exit_code: proc_runtime.exit_code,
+        result: proc_runtime.exit_err.clone().unwrap_or_default(),
+    };
+    if let Err(err) = append_run_record(service_name, &record) {
+        warn!("[{}] save run history failed: {}", service_name, err);
+    }
+}
+
+fn append_run_record(service_name: &str, record: &RunRecord) -> Result<()> {
+    let path = env::get_service_home(service_name).join(RUN_HISTORY_FILE_NAME);
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    drop(file);
+    //超过上限时只保留最近的记录
+    let content = fs::read_to_string(&path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.len() > MAX_RUN_HISTORY {
+        let kept = lines[lines.len() - MAX_RUN_HISTORY..].join("\n");
+        fs::write(&path, kept + "\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(cron: &str, timezone: Option<&str>) -> ScheduleConfig {
+        ScheduleConfig {
+            cron: cron.to_string(),
+            timezone: timezone.map(|t| t.to_string()),
+            overlap: OverlapPolicy::Skip,
+            max_runtime: None,
+        }
+    }
+
+    #[test]
+    fn test_next_fire_time_with_timezone() {
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        //上海时间每天2点，即UTC时间18点
+        let next = next_fire_time(&schedule("0 0 2 * * *", Some("Asia/Shanghai")), after)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 1, 18, 0, 0).unwrap());
+        let next = next_fire_time(&schedule("*/10 * * * * *", Some("UTC")), after)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 10).unwrap());
+    }
+
+    #[test]
+    fn test_next_fire_time_invalid_cron() {
+        let after = Utc::now();
+        assert!(next_fire_time(&schedule("not a cron", None), after).is_err());
+    }
+}
@@ -1,68 +1,259 @@
-use crate::config::{GlobalConfig, ServiceConfig};
+use crate::config::{self, DependsOnCondition, GlobalConfig, ServiceConfig, ServiceType};
 use crate::event::{EventType, ProcessEvent};
 use crate::{env, event};
 use anyhow::{Error, Result};
+use chrono::{DateTime, Local};
 use log::{error, info, warn};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
 use sysinfo::{Pid, System};
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ProcessState {
+    //尚未启动或已被process-compose停止
+    Stopped,
+    Running,
+    //进程自行退出
+    Exited,
+    //连续崩溃超过限制，需要运维人员重置后才能再次启动
+    Failed,
+    //一次性任务成功运行结束
+    Completed,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct ProcessRuntimeInfo {
+    //实例名称，单实例服务与服务名相同
     pub(crate) name: String,
+    //实例所属的服务
+    pub(crate) service: String,
+    pub(crate) replica_index: u32,
     pub(crate) pid: Option<u32>,
     pub(crate) is_child_process: bool,
     pub(crate) health: Option<bool>,
+    //最近一次健康检查结果的原因，如cmd方式检查命令的输出
+    pub(crate) health_message: Option<String>,
+    //就绪检查及存活检查连续成功、连续失败的次数，服务每次启动时清零
+    pub(crate) readiness: HealthCounts,
+    pub(crate) liveness: HealthCounts,
     pub(crate) config: Arc<ServiceConfig>,
     pub(crate) stopped_by_supervisor: bool,
     pub(crate) last_start_time: Option<SystemTime>,
     pub(crate) last_stop_time: Option<SystemTime>,
     pub(crate) exit_err: Option<String>,
+    pub(crate) exit_code: Option<i32>,
+    //连续自动重启的次数，用于计算重启的退避时间
+    pub(crate) restart_attempts: u32,
+    pub(crate) state: ProcessState,
+    //累计自动重启的次数
+    pub(crate) restart_count: u32,
+    //restart_window内的自动重启时间，用于检测崩溃循环
+    pub(crate) restart_history: Vec<SystemTime>,
+    pub(crate) last_failure: Option<String>,
+    //进程在stop_timeout内没有退出，被强制杀掉
+    pub(crate) force_killed: bool,
+}
+
+impl ProcessRuntimeInfo {
+    pub(crate) fn new(name: &str, config: Arc<ServiceConfig>) -> Self {
+        ProcessRuntimeInfo {
+            name: name.to_string(),
+            service: name.to_string(),
+            replica_index: 0,
+            pid: None,
+            is_child_process: true,
+            health: None,
+            health_message: None,
+            readiness: HealthCounts::default(),
+            liveness: HealthCounts::default(),
+            config,
+            stopped_by_supervisor: false,
+            last_start_time: None,
+            last_stop_time: None,
+            exit_err: None,
+            exit_code: None,
+            restart_attempts: 0,
+            state: ProcessState::Stopped,
+            restart_count: 0,
+            restart_history: Vec::new(),
+            last_failure: None,
+            force_killed: false,
+        }
+    }
+}
+
+//检查连续成功及连续失败的次数，一次成功会清零连续失败的次数，反之亦然
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HealthCounts {
+    pub(crate) successes: u32,
+    pub(crate) failures: u32,
+}
+
+impl HealthCounts {
+    fn record(&mut self, success: bool) {
+        if success {
+            self.successes += 1;
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+        }
+    }
+}
+
+//对外展示的服务运行状态
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ProcessStatus {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) service: String,
+    pub(crate) state: ProcessState,
+    pub(crate) pid: Option<u32>,
+    pub(crate) health: Option<bool>,
+    #[serde(default)]
+    pub(crate) health_message: Option<String>,
+    #[serde(default)]
+    pub(crate) readiness: HealthCounts,
+    #[serde(default)]
+    pub(crate) liveness: HealthCounts,
+    pub(crate) is_child_process: bool,
+    pub(crate) stopped_by_supervisor: bool,
+    pub(crate) last_start_time: Option<String>,
+    pub(crate) last_stop_time: Option<String>,
+    pub(crate) exit_err: Option<String>,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) restart_count: u32,
+    pub(crate) last_failure: Option<String>,
+}
+
+impl From<&ProcessRuntimeInfo> for ProcessStatus {
+    fn from(proc: &ProcessRuntimeInfo) -> Self {
+        let format_time =
+            |t: Option<SystemTime>| t.map(|t| DateTime::<Local>::from(t).to_rfc3339());
+        ProcessStatus {
+            name: proc.name.clone(),
+            service: proc.service.clone(),
+            state: proc.state.clone(),
+            pid: proc.pid,
+            health: proc.health,
+            health_message: proc.health_message.clone(),
+            readiness: proc.readiness,
+            liveness: proc.liveness,
+            is_child_process: proc.is_child_process,
+            stopped_by_supervisor: proc.stopped_by_supervisor,
+            last_start_time: format_time(proc.last_start_time),
+            last_stop_time: format_time(proc.last_stop_time),
+            exit_err: proc.exit_err.clone(),
+            exit_code: proc.exit_code,
+            restart_count: proc.restart_count,
+            last_failure: proc.last_failure.clone(),
+        }
+    }
 }
 
 static PROCESSES: RwLock<Vec<RwLock<ProcessRuntimeInfo>>> = RwLock::new(Vec::new());
 
 pub fn init_processes(config: &GlobalConfig, start_orders: Vec<String>) -> Result<()> {
-    let mut process_map: HashMap<String, ServiceConfig> = HashMap::new();
-    for (name, svc_config) in config.services.iter() {
-        process_map.insert(name.clone(), svc_config.clone());
-    }
     let mut processes = PROCESSES.write().unwrap();
-    // 按照启动顺序进行排序
-    for (_, name) in start_orders.iter().enumerate() {
-        if let Some(cfg) = process_map.get(name) {
-            let config = Arc::new(cfg.clone());
-            let mut proc = find_proc_from_pid_file(config.clone());
-            if proc.is_none() {
-                proc = Some(ProcessRuntimeInfo {
-                    name: name.clone(),
-                    pid: None,
-                    health: None,
-                    config: config.clone(),
-                    is_child_process: true,
-                    stopped_by_supervisor: false,
-                    last_start_time: None,
-                    last_stop_time: None,
-                    exit_err: None,
-                });
-            }
-            processes.push(RwLock::new(proc.unwrap()));
-        } else {
-            return Err(Error::msg(format!(
+    // 按照启动顺序进行排序，同一服务的实例按照序号排列
+    for name in start_orders.iter() {
+        let service = config.services.get(name).ok_or_else(|| {
+            Error::msg(format!(
                 "service {} was not found in the configuration.",
                 name
-            )));
+            ))
+        })?;
+        let instances = config::instance_configs(service, service.replicas)?;
+        for (index, instance) in instances.into_iter().enumerate() {
+            let proc = new_process(name, index as u32, instance);
+            processes.push(RwLock::new(proc));
         }
     }
     Ok(())
 }
 
+fn new_process(
+    service_name: &str,
+    replica_index: u32,
+    instance: ServiceConfig,
+) -> ProcessRuntimeInfo {
+    let config = Arc::new(instance);
+    let proc = find_proc_from_pid_file(config.clone())
+        .unwrap_or_else(|| ProcessRuntimeInfo::new(&config.name, config.clone()));
+    ProcessRuntimeInfo {
+        service: service_name.to_string(),
+        replica_index,
+        ..proc
+    }
+}
+
+//加入服务的一个实例，用于热加载配置及扩容，返回实例名称
+pub(crate) fn add_process(service: &ServiceConfig, index: u32) -> Result<String> {
+    let instance = config::instance_config(service, index)?;
+    env::create_services_home(&vec![instance.clone()])?;
+    let name = instance.name.clone();
+    let proc = new_process(&service.name, index, instance);
+    PROCESSES.write().unwrap().push(RwLock::new(proc));
+    Ok(name)
+}
+
+//测试中直接添加服务实例，不读取pid文件也不创建服务目录
+#[cfg(test)]
+pub(crate) fn add_test_process(config: ServiceConfig) {
+    let name = config.name.clone();
+    let proc = ProcessRuntimeInfo::new(&name, Arc::new(config));
+    PROCESSES.write().unwrap().push(RwLock::new(proc));
+}
+
+//移除服务的一个实例，用于热加载配置及缩容
+pub(crate) fn remove_process(instance_name: &str) {
+    PROCESSES
+        .write()
+        .unwrap()
+        .retain(|p| p.read().unwrap().name != instance_name);
+}
+
+//服务当前所有实例的名称，按照实例序号排列
+pub(crate) fn find_service_instances(service_name: &str) -> Vec<String> {
+    let processes = PROCESSES.read().unwrap();
+    let mut instances: Vec<(u32, String)> = processes
+        .iter()
+        .map(|p| p.read().unwrap())
+        .filter(|p| p.service == service_name)
+        .map(|p| (p.replica_index, p.name.clone()))
+        .collect();
+    instances.sort();
+    instances.into_iter().map(|(_, name)| name).collect()
+}
+
+//服务名既可以是某个实例，也可以是包含多个实例的服务
+pub(crate) fn service_exists(name: &str) -> bool {
+    find_readonly_proc_runtime(name).is_ok() || !find_service_instances(name).is_empty()
+}
+
+//按照新的启动顺序重新排列服务
+pub(crate) fn sort_processes(start_orders: &[String]) {
+    let mut processes = PROCESSES.write().unwrap();
+    processes.sort_by_key(|p| {
+        let proc = p.read().unwrap();
+        let order = start_orders
+            .iter()
+            .position(|n| *n == proc.service)
+            .unwrap_or(usize::MAX);
+        (order, proc.replica_index)
+    });
+}
+
 pub fn is_running_by_name(service_name: &str) -> bool {
-    let proc_runtime = find_readonly_proc_runtime(service_name).unwrap();
-    let pid = proc_runtime.pid;
+    //未知的服务视为未运行
+    let pid = match find_readonly_proc_runtime(service_name) {
+        Ok(proc_runtime) => proc_runtime.pid,
+        Err(_) => return false,
+    };
     if pid.is_none() {
         return false;
     }
@@ -77,20 +268,27 @@ pub fn is_running_by_pid(pid: u32) -> bool {
 }
 
 //更新服务进程的健康状态
-pub fn change_proc_health_status(name: &str, health: bool) -> Result<()> {
+pub fn change_proc_health_status(name: &str, health: bool, message: Option<String>) -> Result<()> {
     update_proc_runtime(name, |proc| {
         if proc.health.is_none() || proc.health.unwrap() != health {
             info!("service [{}] health changed to {}", name, health)
         }
         proc.health = Some(health);
+        proc.health_message = message.clone();
     })?;
     Ok(())
 }
 
-//查询某个服务的健康状态
-pub fn is_heathy(name: &str) -> Option<bool> {
-    let proc_runtime = find_readonly_proc_runtime(name).unwrap();
-    return proc_runtime.health;
+//记录一次就绪检查的结果，返回记录后的连续次数
+pub(crate) fn record_readiness_check(name: &str, success: bool) -> Result<HealthCounts> {
+    update_proc_runtime(name, |proc| proc.readiness.record(success))?;
+    Ok(find_readonly_proc_runtime(name)?.readiness)
+}
+
+//记录一次存活检查的结果，返回记录后的连续次数
+pub(crate) fn record_liveness_check(name: &str, success: bool) -> Result<HealthCounts> {
+    update_proc_runtime(name, |proc| proc.liveness.record(success))?;
+    Ok(find_readonly_proc_runtime(name)?.liveness)
 }
 
 pub fn check_dep_ok(name: &str) -> bool {
@@ -103,14 +301,44 @@ pub fn check_dep_ok(name: &str) -> bool {
         return true;
     }
     let deps = deps.unwrap();
-    for dep in deps {
-        if !is_heathy(&dep).unwrap_or(false) {
+    //依赖服务的所有实例都需要满足条件
+    for (dep, dep_config) in deps {
+        let instances = find_service_instances(&dep);
+        if instances.is_empty() {
             return false;
         }
+        for instance in instances {
+            match find_readonly_proc_runtime(&instance) {
+                Ok(dep_runtime) if is_dep_condition_met(&dep_runtime, &dep_config.condition) => {}
+                _ => return false,
+            }
+        }
     }
     return true;
 }
 
+//判断依赖服务当前的状态是否满足启动条件
+pub(crate) fn is_dep_condition_met(
+    dep_runtime: &ProcessRuntimeInfo,
+    condition: &DependsOnCondition,
+) -> bool {
+    //一次性任务只有成功运行结束才满足依赖条件，运行中或失败时依赖它的服务保持等待
+    if dep_runtime.config.service_type == ServiceType::Oneshot {
+        return dep_runtime.state == ProcessState::Completed;
+    }
+    match condition {
+        DependsOnCondition::ServiceStarted => dep_runtime.state == ProcessState::Running,
+        //未配置健康检查的服务永远不会变为健康状态，此时只要求其处于运行状态
+        DependsOnCondition::ServiceHealthy => match dep_runtime.config.readiness_check() {
+            Some(_) => dep_runtime.health == Some(true),
+            None => dep_runtime.state == ProcessState::Running,
+        },
+        DependsOnCondition::ServiceCompletedSuccessfully => {
+            dep_runtime.state == ProcessState::Exited && dep_runtime.exit_code == Some(0)
+        }
+    }
+}
+
 // 更新服务进程的运行状态至启动
 pub(crate) fn update_proc_to_started(
     service_name: &str,
@@ -121,7 +349,9 @@ pub(crate) fn update_proc_to_started(
         proc.pid = Some(pid);
         proc.last_start_time = Some(SystemTime::now());
         proc.stopped_by_supervisor = false;
+        proc.force_killed = false;
         proc.is_child_process = is_child_process;
+        proc.state = ProcessState::Running;
     })?;
     fs::write(
         env::get_service_home(service_name).join("pid"),
@@ -132,21 +362,73 @@ pub(crate) fn update_proc_to_started(
     Ok(())
 }
 
+// 服务进程无法启动时按照自行退出处理，以便重启策略生效
+pub(crate) fn update_proc_to_spawn_failed(service_name: &str, err_msg: &str) -> Result<()> {
+    update_proc_runtime(service_name, |proc| {
+        proc.pid = None;
+        proc.stopped_by_supervisor = false;
+        proc.last_stop_time = Some(SystemTime::now());
+        proc.exit_err = Some(err_msg.to_string());
+        proc.exit_code = None;
+        proc.state = ProcessState::Exited;
+    })?;
+    event::send_process_event(
+        service_name,
+        EventType::Exited,
+        Some(err_msg.to_string()),
+        None,
+    );
+    Ok(())
+}
+
+// 将服务标记为失败状态，失败的服务不会再被自动拉起
+pub(crate) fn update_proc_to_failed(service_name: &str, reason: &str) -> Result<()> {
+    update_proc_runtime(service_name, |proc| {
+        proc.state = ProcessState::Failed;
+        proc.last_failure = Some(reason.to_string());
+    })?;
+    event::send_process_event(
+        service_name,
+        EventType::Failed,
+        Some(reason.to_string()),
+        None,
+    );
+    Ok(())
+}
+
 // 更新服务进程的运行状态至停止
-pub(crate) fn update_proc_to_stopped(service_name: &str, exit_msg: &str, pid: u32) -> Result<()> {
+pub(crate) fn update_proc_to_stopped(
+    service_name: &str,
+    exit_msg: &str,
+    exit_code: Option<i32>,
+    pid: u32,
+) -> Result<()> {
+    let proc_info = find_readonly_proc_runtime(service_name)?;
+    let exit_msg = if proc_info.force_killed {
+        format!(
+            "{} (force killed after stop_timeout {}s)",
+            exit_msg, proc_info.config.stop_timeout
+        )
+    } else {
+        exit_msg.to_string()
+    };
     update_proc_runtime(service_name, |proc| {
         proc.pid = None;
         proc.last_stop_time = Some(SystemTime::now());
-        proc.exit_err = Some(exit_msg.to_string());
+        proc.exit_err = Some(exit_msg.clone());
+        proc.exit_code = exit_code;
     })?;
     fs::remove_file(env::get_service_home(service_name).join("pid"))
         .unwrap_or_else(|e| warn!("{} remove pid file failed:{}", service_name, e));
     let proc_info = find_readonly_proc_runtime(service_name)?;
-    let event_type = if proc_info.stopped_by_supervisor {
-        EventType::Stopped
+    let (event_type, state) = if proc_info.stopped_by_supervisor {
+        (EventType::Stopped, ProcessState::Stopped)
+    } else if proc_info.config.service_type == ServiceType::Oneshot && exit_code == Some(0) {
+        (EventType::Completed, ProcessState::Completed)
     } else {
-        EventType::Exited
+        (EventType::Exited, ProcessState::Exited)
     };
+    update_proc_runtime(service_name, |proc| proc.state = state.clone())?;
     event::send_process_event(
         service_name,
         event_type.clone(),
@@ -156,6 +438,43 @@ pub(crate) fn update_proc_to_stopped(service_name: &str, exit_msg: &str, pid: u3
     Ok(())
 }
 
+// 重置失败状态及重启计数，使服务可以再次启动
+pub(crate) fn reset_proc_failure(service_name: &str) -> Result<()> {
+    update_proc_runtime(service_name, |proc| {
+        if proc.state == ProcessState::Failed {
+            proc.state = ProcessState::Stopped;
+        }
+        proc.restart_attempts = 0;
+        proc.restart_history.clear();
+        proc.last_failure = None;
+    })
+}
+
+// 记录服务是否被运维人员主动停止，process-compose重启后unless-stopped策略的服务依据该标记决定是否启动
+pub(crate) fn mark_stopped_by_operator(service_name: &str, stopped: bool) {
+    let marker = env::get_service_home(service_name).join("stopped");
+    let r = if stopped {
+        fs::write(&marker, b"")
+    } else if marker.exists() {
+        fs::remove_file(&marker)
+    } else {
+        Ok(())
+    };
+    r.unwrap_or_else(|e| warn!("{} update stopped marker failed:{}", service_name, e));
+}
+
+pub(crate) fn is_stopped_by_operator(service_name: &str) -> bool {
+    env::get_service_home(service_name).join("stopped").exists()
+}
+
+pub(crate) fn get_all_proc_status() -> Vec<ProcessStatus> {
+    let processes = PROCESSES.read().unwrap();
+    processes
+        .iter()
+        .map(|p| ProcessStatus::from(&*p.read().unwrap()))
+        .collect()
+}
+
 fn find_proc_from_pid_file(service_config: Arc<ServiceConfig>) -> Option<ProcessRuntimeInfo> {
     let pid_path = env::get_service_home(&service_config.name).join("pid");
     if pid_path.exists() {
@@ -163,15 +482,11 @@ fn find_proc_from_pid_file(service_config: Arc<ServiceConfig>) -> Option<Process
         let pid = pid_str.trim().parse::<u32>().unwrap();
         if is_running_by_pid(pid) {
             return Some(ProcessRuntimeInfo {
-                name: service_config.name.clone(),
                 pid: Some(pid),
-                health: None,
                 is_child_process: false,
-                config: service_config,
-                stopped_by_supervisor: false,
+                state: ProcessState::Running,
                 last_start_time: Some(SystemTime::now()),
-                last_stop_time: None,
-                exit_err: None,
+                ..ProcessRuntimeInfo::new(&service_config.name, service_config.clone())
             });
         }
     }
@@ -221,3 +536,143 @@ where
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HealthCheckConfig;
+
+    fn runtime_with(state: ProcessState, exit_code: Option<i32>) -> ProcessRuntimeInfo {
+        let config = ServiceConfig {
+            name: "db".to_string(),
+            ..Default::default()
+        };
+        ProcessRuntimeInfo {
+            state,
+            exit_code,
+            ..ProcessRuntimeInfo::new("db", Arc::new(config))
+        }
+    }
+
+    #[test]
+    fn test_dep_condition() {
+        let running = runtime_with(ProcessState::Running, None);
+        assert!(is_dep_condition_met(
+            &running,
+            &DependsOnCondition::ServiceStarted
+        ));
+        //没有健康检查的依赖服务运行即视为健康
+        assert!(is_dep_condition_met(
+            &running,
+            &DependsOnCondition::ServiceHealthy
+        ));
+        assert!(!is_dep_condition_met(
+            &running,
+            &DependsOnCondition::ServiceCompletedSuccessfully
+        ));
+
+        let mut checked = running.clone();
+        checked.config = Arc::new(ServiceConfig {
+            healthcheck: Some(HealthCheckConfig {
+                test_type: crate::health::HealthCheckType::Proccess,
+                test_target: "".to_string(),
+                test_cmd: Vec::new(),
+                interval: 5,
+                max_failures: 1,
+                success_threshold: 1,
+                start_period: None,
+                timeout: None,
+                log_timeout: None,
+                http: Default::default(),
+            }),
+            ..Default::default()
+        });
+        assert!(!is_dep_condition_met(
+            &checked,
+            &DependsOnCondition::ServiceHealthy
+        ));
+        checked.health = Some(true);
+        assert!(is_dep_condition_met(
+            &checked,
+            &DependsOnCondition::ServiceHealthy
+        ));
+
+        let completed = runtime_with(ProcessState::Exited, Some(0));
+        assert!(is_dep_condition_met(
+            &completed,
+            &DependsOnCondition::ServiceCompletedSuccessfully
+        ));
+        //一次性任务完成后满足任意条件
+        let completed_oneshot = oneshot_with(ProcessState::Completed, Some(0));
+        assert!(is_dep_condition_met(
+            &completed_oneshot,
+            &DependsOnCondition::ServiceHealthy
+        ));
+        assert!(is_dep_condition_met(
+            &completed_oneshot,
+            &DependsOnCondition::ServiceCompletedSuccessfully
+        ));
+        let crashed = runtime_with(ProcessState::Exited, Some(1));
+        assert!(!is_dep_condition_met(
+            &crashed,
+            &DependsOnCondition::ServiceCompletedSuccessfully
+        ));
+    }
+
+    fn oneshot_with(state: ProcessState, exit_code: Option<i32>) -> ProcessRuntimeInfo {
+        let mut proc_runtime = runtime_with(state, exit_code);
+        proc_runtime.config = Arc::new(ServiceConfig {
+            name: "migrate".to_string(),
+            service_type: ServiceType::Oneshot,
+            ..Default::default()
+        });
+        proc_runtime
+    }
+
+    #[test]
+    fn test_running_oneshot_keeps_dependents_pending() {
+        let conditions = [
+            DependsOnCondition::ServiceStarted,
+            DependsOnCondition::ServiceHealthy,
+            DependsOnCondition::ServiceCompletedSuccessfully,
+        ];
+        let running = oneshot_with(ProcessState::Running, None);
+        let failed = oneshot_with(ProcessState::Exited, Some(1));
+        for condition in conditions.iter() {
+            assert!(!is_dep_condition_met(&running, condition));
+            assert!(!is_dep_condition_met(&failed, condition));
+        }
+    }
+
+    #[test]
+    fn test_health_counts_are_consecutive() {
+        let mut counts = HealthCounts::default();
+        counts.record(false);
+        counts.record(false);
+        assert_eq!(
+            counts,
+            HealthCounts {
+                successes: 0,
+                failures: 2
+            }
+        );
+        //一次成功清零连续失败的次数
+        counts.record(true);
+        assert_eq!(
+            counts,
+            HealthCounts {
+                successes: 1,
+                failures: 0
+            }
+        );
+        counts.record(true);
+        counts.record(false);
+        assert_eq!(
+            counts,
+            HealthCounts {
+                successes: 0,
+                failures: 1
+            }
+        );
+    }
+}
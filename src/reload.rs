@@ -0,0 +1,116 @@
+use std::{sync::Mutex, thread, time::Duration};
+
+use anyhow::{Error, Result};
+use log::{error, info, warn};
+
+use crate::{
+    config::{self, analyze_service_dependencies},
+    health, logger,
+    process::{self, manager, pending, schedule, status},
+};
+
+//SIGHUP和reload命令可能同时触发重新加载，需要串行执行
+static RELOAD_LOCK: Mutex<()> = Mutex::new(());
+
+//重新加载config.yaml：新增的服务会被启动，删除的服务会被停止，只有配置发生变化的服务才会被重启。
+//新配置无效时保持当前配置不变
+pub fn reload() -> Result<String> {
+    let _guard = RELOAD_LOCK.lock().unwrap();
+    let mut new_config = config::read_config()
+        .map_err(|e| Error::msg(format!("invalid config, keep the current one: {}", e)))?;
+    let old_config = config::current_config();
+    //以下配置需要重启process-compose才能生效
+    if new_config.app_data_home != old_config.app_data_home
+        || new_config.sys_service_name != old_config.sys_service_name
+        || new_config.sys_service_desc != old_config.sys_service_desc
+        || new_config.api != old_config.api
+    {
+        warn!("app_data_home, sys_service_* and api can not be reloaded, restart process-compose to apply them");
+        new_config.app_data_home = old_config.app_data_home.clone();
+        new_config.sys_service_name = old_config.sys_service_name.clone();
+        new_config.sys_service_desc = old_config.sys_service_desc.clone();
+        new_config.api = old_config.api.clone();
+    }
+    let services: Vec<_> = new_config.services.values().cloned().collect();
+    let start_orders = analyze_service_dependencies(&services)?;
+    let diff = config::diff_services(&old_config.services, &new_config.services);
+    config::set_config(new_config.clone());
+    if new_config.log_level != old_config.log_level {
+        logger::change_log_level(&new_config.log_level);
+        info!("log level changed to {}", new_config.log_level);
+    }
+    for name in diff.removed.iter() {
+        info!("[{}] was removed from config, stopping it", name);
+        remove_instances(name);
+    }
+    //配置变化的服务按照新配置重建所有实例，运行中或等待依赖启动的服务需要重新启动
+    let mut restarting = Vec::new();
+    for name in diff.changed.iter() {
+        let was_active = status::find_service_instances(name)
+            .iter()
+            .any(|instance| status::is_running_by_name(instance) || pending::is_pending(instance));
+        if was_active {
+            info!("[{}] config changed, restarting it", name);
+            restarting.push(name.clone());
+        }
+        remove_instances(name);
+    }
+    for name in diff.added.iter().chain(diff.changed.iter()) {
+        let service = &new_config.services[name];
+        for index in 0..service.replicas {
+            status::add_process(service, index)?;
+        }
+    }
+    status::sort_processes(&start_orders);
+    //按照依赖顺序启动新增的服务及需要重启的服务
+    let to_start: Vec<String> = start_orders
+        .into_iter()
+        .filter(|name| diff.added.contains(name) || restarting.contains(name))
+        .filter(|name| !schedule::is_scheduled(name))
+        .flat_map(|name| status::find_service_instances(&name))
+        .collect();
+    process::manager::start_services(to_start)?;
+    schedule::sync_schedulers();
+    let summary = format!(
+        "config reloaded, added: {:?}, removed: {:?}, changed: {:?}",
+        diff.added, diff.removed, diff.changed
+    );
+    info!("{}", summary);
+    Ok(summary)
+}
+
+//停止并移除服务的所有实例
+fn remove_instances(service_name: &str) {
+    for instance in status::find_service_instances(service_name) {
+        stop_and_wait(&instance);
+        pending::remove_pending_service(&instance);
+        health::stop_watch(instance.clone());
+        status::remove_process(&instance);
+    }
+}
+
+fn stop_and_wait(service_name: &str) {
+    manager::stop_service(service_name)
+        .unwrap_or_else(|e| error!("stop [{}] failed: {}", service_name, e));
+    manager::wait_for_exit_recorded(service_name, Duration::from_secs(5));
+}
+
+//收到SIGHUP信号时重新加载配置
+#[cfg(unix)]
+pub fn watch_reload_signal() -> Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("received SIGHUP, reloading config...");
+            if let Err(err) = reload() {
+                error!("reload config failed: {}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn watch_reload_signal() -> Result<()> {
+    Ok(())
+}